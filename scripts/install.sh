@@ -217,86 +217,11 @@ if [ ! -f /opt/xornet/config.json ]; then
   cd $path
 fi
 
-if [ $service_manager = "systemd" ]; then
-  if [ ! -f /etc/systemd/system/xornet-reporter.service ]; then
-
-    # Create the systemd service
-    echo "Creating the systemd service..."
-    cat >/etc/systemd/system/xornet-reporter.service <<EOF
-[Unit]
-Description=Xornet reporter
-After=network.target
-
-[Service]
-Type=simple
-User=root
-WorkingDirectory=/opt/xornet
-ExecStart=/opt/xornet/xornet-reporter --silent
-Restart=always
-RestartSec=10
-
-[Install]
-WantedBy=multi-user.target
-EOF
-    echo "Ok."
-    echo
-
-    # Enable the service
-    echo "Enabling the systemd service..."
-    systemctl enable xornet-reporter.service
-    echo "Ok."
-    echo
-  fi
-
-  # Start the service
-  echo "Starting the systemd service..."
-  systemctl start xornet-reporter.service
-  echo "Ok."
-  echo
-
-elif [ $service_manager = "openrc" ]; then
-  if [ ! -f /etc/init.d/xornet-reporter ]; then
-
-    # Create the openrc service
-    echo "Creating the openrc service..."
-    cat >/etc/init.d/xornet-reporter <<EOF
-#!/sbin/openrc-run
-command=/opt/xornet/xornet-reporter
-command_args="--silent"
-command_user="root:root"
-pidfile=/var/run/xornet-reporter.pid
-directory="/opt/xornet/"
-supervisor=supervise-daemon
-supervise_daemon_args="--respawn-delay 5 --pidfile /var/run/xornet-reporter.pid"
-name="Xornet Reporter service"
-
-description="Xornet Reporter is a service for Xornet status reporting"
-
-depend() {
-	need net
-  after localmount
-}
-
-EOF
-    echo "Ok."
-    echo
-
-    echo "Marking the file as executable..."
-    chmod +x /etc/init.d/xornet-reporter
-    handle_exit_code
-
-    # Add the service to default runlevel
-    echo "Enabling the openrc service to default runlevel..."
-    rc-update add xornet-reporter default
-    echo "Ok."
-    echo
-  fi
-
-  # Start the service
-  echo "Starting the xornet-reporter service..."
-  rc-service xornet-reporter start
-  echo "Ok."
-  echo
+if [ $service_manager != "none" ]; then
+  # The reporter generates the service, creates its user and starts it itself
+  echo "Installing the ${service_manager} service..."
+  /opt/xornet/xornet-reporter service install --init $service_manager
+  handle_exit_code
 fi
 cleanup
 
@@ -57,14 +57,8 @@ case ${answer:0:1} in
   y|Y )
     echo "Ok, uninstalling Xornet reporter..."
     if [ $systemctl_installed = true ]; then
-      echo "Stopping Xornet reporter service..."
-      systemctl stop xornet-reporter.service
-      handle_exit_code_non_crucial
-      echo "Disabling Xornet reporter service..."
-      systemctl disable xornet-reporter.service
-      handle_exit_code_non_crucial
       echo "Removing Xornet reporter service..."
-      rm /etc/systemd/system/xornet-reporter.service
+      /opt/xornet/xornet-reporter service uninstall --init systemd
       handle_exit_code_non_crucial
     fi
     echo "Removing Xornet reporter files..."
@@ -1,22 +1,37 @@
 use anyhow::Result;
 use colored::Colorize;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
 
+#[cfg(feature = "token-endpoints")]
+use crate::auth_manager::TokenStatus;
 use crate::{
-  auth_manager::AuthManager, config_manager::ConfigManager, data_collector::DataCollector,
+  auth_manager::AuthManager,
+  config_manager::{is_positive_seconds, ConfigManager, SECRETS_FILE},
+  data_collector::DataCollector,
+  logger::Format,
+  service_manager::{InitSystem, ServiceManager},
 };
 
 /// The structure of the launch parameters.
+/// These override the collection settings in the config when given.
 #[derive(Debug, Clone)]
 pub struct ArgParser {
-  pub interval: f64,
+  pub interval: Option<f64>,
   pub offline: bool,
+  /// How many levels more (--verbose) or less (-q) verbose to log than the config says
+  pub verbosity: i8,
+  pub log_format: Option<Format>,
 }
 
 impl ArgParser {
   pub async fn new() -> Result<ArgParser> {
     let mut arg_parser = ArgParser {
-      interval: 1.0,
+      interval: None,
       offline: false,
+      verbosity: 0,
+      log_format: None,
     };
     let args: Vec<String> = std::env::args().collect();
     let mut index: usize = 0;
@@ -39,15 +54,63 @@ impl ArgParser {
             "    -su, --signup <key>                 : {}",
             "Sign up the machine with an authentication key to Xornet for online features".white()
           );
+          println!(
+            "    -as, --auth-status                  : {}",
+            "Show whether the machine is signed up and where its token is stored".white()
+          );
+          #[cfg(feature = "token-endpoints")]
+          {
+            println!(
+              "    -rt, --rotate-token                 : {}",
+              "Replace the access token with a new one from Xornet".white()
+            );
+            println!(
+              "    -rv, --revoke-token                 : {}",
+              "Revoke the access token on Xornet and remove it locally".white()
+            );
+          }
+          println!(
+            "    -lo, --logout                       : {}",
+            "Remove the access token from this machine".white()
+          );
           println!(
             "    -i,  --interval   {}      : {}",
             "(default: 1)".bright_black(),
-            "Data collection interval in seconds".white()
+            "Data collection interval in seconds, overrides the config".white()
           );
           println!(
             "    -off,  --offline  {}  : {}",
             "(default: false)".bright_black(),
-            "Disables sending data to Xornet's backend".white()
+            "Disables sending data to Xornet's backend, overrides the config".white()
+          );
+          println!(
+            "    -vb, --verbose                      : {}",
+            "Log more, twice for everything, overrides the config".white()
+          );
+          println!(
+            "    -q,  --quiet                        : {}",
+            "Log less, -qq for errors only, overrides the config".white()
+          );
+          println!(
+            "    -lf, --log-format <format>          : {}",
+            "Log as text, json or to journald, overrides the config".white()
+          );
+          println!(
+            "    -jd, --journald                     : {}",
+            "Same as --log-format journald".white()
+          );
+          println!("\n{} Commands:", "●".cyan());
+          println!(
+            "    service install|uninstall|status    : {}",
+            "Manage the systemd or OpenRC service (Linux only)".white()
+          );
+          println!(
+            "      --dry-run                         : {}",
+            "Print the files and commands instead of applying them".white()
+          );
+          println!(
+            "      --init systemd|openrc             : {}",
+            "Use this init system instead of detecting it".white()
           );
           println!("\n{} Examples:", "●".magenta());
           println!("    {} {}", "$".bright_black(), "xornet".yellow());
@@ -57,6 +120,11 @@ impl ArgParser {
             "$".bright_black(),
             "xornet".yellow(),
           );
+          println!(
+            "    {} {} service install --dry-run",
+            "$".bright_black(),
+            "xornet".yellow(),
+          );
           println!(
             "\n    {}",
             "More info at https://github.com/xornet-cloud/Reporter".bright_black()
@@ -65,13 +133,43 @@ impl ArgParser {
           println!();
           std::process::exit(0);
         }
-        "-v" | "--version" => {
+        // -V is accepted as well since that's what most tools use
+        "-v" | "-V" | "--version" => {
           println!("xornet v{}", env!("CARGO_PKG_VERSION"));
           std::process::exit(0);
         }
         "-off" | "--offline" => {
           arg_parser.offline = true;
         }
+        "-jd" | "--journald" => {
+          arg_parser.log_format = Some(Format::Journald);
+        }
+        "-vb" | "--verbose" => {
+          arg_parser.verbosity += 1;
+        }
+        "-q" | "--quiet" => {
+          arg_parser.verbosity -= 1;
+        }
+        "-qq" => {
+          arg_parser.verbosity -= 2;
+        }
+        "-lf" | "--log-format" => {
+          let format = match args.get(index + 1).map(String::as_str) {
+            Some("text") => Format::Text,
+            Some("json") => Format::Json,
+            Some("journald") => Format::Journald,
+            _ => {
+              println!(
+                "{}",
+                "Missing or invalid argument for option -lf <text|json|journald>, use -h for help"
+                  .red()
+              );
+              std::process::exit(1);
+            }
+          };
+          index += 1;
+          arg_parser.log_format = Some(format);
+        }
         "-su" | "--signup" => {
           if args.len() > index + 1 {
             index += 1;
@@ -101,9 +199,9 @@ impl ArgParser {
               Ok(response) => {
                 ConfigManager::save_access_token(&response.access_token)?;
                 println!(
-                  "{} {}\n",
+                  "{} access token saved to {}\n",
                   "Signup successful:".green(),
-                  response.access_token
+                  SECRETS_FILE
                 );
                 println!(
                                     "You can now start the reporter with the following command: \n    $ xornet --silent",
@@ -122,9 +220,17 @@ impl ArgParser {
         "-i" | "--interval" => {
           if args.len() > index + 1 {
             index += 1;
-            arg_parser.interval = args[index]
-              .parse::<f64>()
-              .expect("Could not parse interval as integer");
+            match args[index].parse::<f64>() {
+              Ok(interval) if is_positive_seconds(interval) => arg_parser.interval = Some(interval),
+              _ => {
+                println!(
+                  "{} {}",
+                  "Invalid interval, use a positive number of seconds:".red(),
+                  args[index].red()
+                );
+                std::process::exit(1);
+              }
+            }
           } else {
             println!(
               "{}",
@@ -133,6 +239,142 @@ impl ArgParser {
             std::process::exit(1);
           }
         }
+        "-as" | "--auth-status" => {
+          let config = ConfigManager::new()?.config;
+          if config.access_token.is_empty() {
+            println!("{} {}", "Access token:".yellow(), "not set".red());
+            std::process::exit(1);
+          }
+          println!("{} {}", "Access token:".yellow(), "set".green());
+          let path = fs::canonicalize(SECRETS_FILE).unwrap_or_else(|_| SECRETS_FILE.into());
+          println!("{} {}", "Stored in:".yellow(), path.display());
+          #[cfg(unix)]
+          {
+            let mode = fs::metadata(SECRETS_FILE)?.permissions().mode() & 0o777;
+            // Anything but the owner being able to read it exposes the token
+            if mode & 0o077 == 0 {
+              println!(
+                "{} {}",
+                "Permissions:".yellow(),
+                format!("{:o}", mode).green()
+              );
+            } else {
+              println!(
+                "{} {} {}",
+                "Permissions:".yellow(),
+                format!("{:o}", mode).red(),
+                format!("other users can read it, run chmod 600 {}", path.display()).red()
+              );
+            }
+          }
+
+          // The backend can only be asked by reporters built with the endpoints it doesn't have yet
+          #[cfg(not(feature = "token-endpoints"))]
+          std::process::exit(0);
+          #[cfg(feature = "token-endpoints")]
+          match AuthManager::status(&config.backend_hostname, &config.access_token).await {
+            Ok(TokenStatus::Valid) => {
+              println!("{} {}", "Backend:".yellow(), "token is valid".green());
+              std::process::exit(0)
+            }
+            Ok(TokenStatus::Invalid) => {
+              println!("{} {}", "Backend:".yellow(), "token was rejected".red());
+              std::process::exit(1)
+            }
+            Err(error) => {
+              println!("{} {}", "Backend:".yellow(), error.to_string().red());
+              std::process::exit(1)
+            }
+          }
+        }
+        #[cfg(feature = "token-endpoints")]
+        "-rt" | "--rotate-token" => {
+          let config = ConfigManager::new()?.config;
+          match AuthManager::rotate(&config.backend_hostname, &config.access_token).await {
+            Ok(response) => {
+              ConfigManager::save_access_token(&response.access_token)?;
+              println!(
+                "{} new access token saved to {}",
+                "Token rotated:".green(),
+                SECRETS_FILE
+              );
+              std::process::exit(0)
+            }
+            Err(error) => {
+              println!("{} {}", "Rotating failed:".red(), error.to_string().red());
+              std::process::exit(1)
+            }
+          }
+        }
+        #[cfg(feature = "token-endpoints")]
+        "-rv" | "--revoke-token" => {
+          let config = ConfigManager::new()?.config;
+          match AuthManager::revoke(&config.backend_hostname, &config.access_token).await {
+            Ok(_) => {
+              ConfigManager::remove_access_token()?;
+              println!("{}", "Token revoked and removed from this machine".green());
+              std::process::exit(0)
+            }
+            Err(error) => {
+              println!("{} {}", "Revoking failed:".red(), error.to_string().red());
+              std::process::exit(1)
+            }
+          }
+        }
+        #[cfg(not(feature = "token-endpoints"))]
+        "-rt" | "--rotate-token" | "-rv" | "--revoke-token" => {
+          println!(
+            "{} {}",
+            arg.red(),
+            "needs a reporter built with the token-endpoints feature".red()
+          );
+          std::process::exit(1);
+        }
+        "-lo" | "--logout" => {
+          ConfigManager::remove_access_token()?;
+          println!(
+            "{}",
+            "Logged out, the token was removed from this machine but is still valid on Xornet until revoked".green()
+          );
+          std::process::exit(0)
+        }
+        "service" => {
+          let action = args.get(index + 1).map(String::as_str).unwrap_or("");
+          let (mut dry_run, mut init_system) = (false, None);
+          let mut option_index = index + 2;
+          while option_index < args.len() {
+            match args[option_index].as_str() {
+              "--dry-run" => dry_run = true,
+              "--init" if option_index + 1 < args.len() => {
+                option_index += 1;
+                init_system = Some(InitSystem::from_name(&args[option_index])?);
+              }
+              option => {
+                println!("{} {}", "Unknown service option:".red(), option.red());
+                std::process::exit(1);
+              }
+            }
+            option_index += 1;
+          }
+
+          let result =
+            ServiceManager::new(init_system, dry_run).and_then(|service_manager| match action {
+              "install" => service_manager.install().map(|_| 0),
+              "uninstall" => service_manager.uninstall().map(|_| 0),
+              "status" => service_manager.status(),
+              _ => Err(anyhow::anyhow!(
+                "Missing or unknown service action, use install, uninstall or status"
+              )),
+            });
+
+          match result {
+            Ok(code) => std::process::exit(code),
+            Err(error) => {
+              println!("{} {}", "Service error:".red(), error.to_string().red());
+              std::process::exit(1);
+            }
+          }
+        }
         "-hs" | "--host" => {
           if args.len() > index + 1 {
             index += 1;
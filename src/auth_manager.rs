@@ -1,7 +1,12 @@
 use anyhow::Result;
+use reqwest::{Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
 
-#[derive(Serialize, Debug)]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Serialize)]
 pub struct SignupBody {
   pub two_factor_key: String,
   pub hostname: String,
@@ -18,6 +23,34 @@ pub struct SignupResponseError {
   pub error: String,
 }
 
+#[derive(Error, Debug)]
+pub enum AuthError {
+  #[error("The signup key was rejected by Xornet: {0}")]
+  InvalidKey(String),
+  #[error("This machine's UUID is already registered with Xornet, keep its current token")]
+  AlreadyRegistered,
+  #[cfg(feature = "token-endpoints")]
+  #[error("The access token was rejected by Xornet, sign up again with --signup <key>")]
+  InvalidToken,
+  #[cfg(feature = "token-endpoints")]
+  #[error("This machine isn't signed up, use --signup <key> first")]
+  NotSignedUp,
+  #[error("Could not reach Xornet at {0}: {1}")]
+  Unreachable(String, reqwest::Error),
+  #[error("Unexpected response from Xornet ({0}): {1}")]
+  Backend(StatusCode, String),
+  #[error("Request to Xornet failed: {0}")]
+  Request(#[from] reqwest::Error),
+}
+
+/// Whether the backend accepts the access token
+#[cfg(feature = "token-endpoints")]
+#[derive(Debug, PartialEq)]
+pub enum TokenStatus {
+  Valid,
+  Invalid,
+}
+
 #[derive(Debug)]
 pub struct AuthManager {}
 
@@ -28,37 +61,137 @@ impl AuthManager {
     hostname: &str,
     backend_hostname: &str,
     hardware_uuid: &str,
-  ) -> Result<SignupResponse> {
-    println!("Signing up to Xornet...");
+  ) -> Result<SignupResponse, AuthError> {
+    info!("Signing up to Xornet...");
 
-    let client = reqwest::Client::new();
     let url = format!("https://{}/machines/@signup", backend_hostname);
     let body = SignupBody {
       two_factor_key: two_factor_key.to_string(),
       hostname: hostname.to_string(),
       hardware_uuid: hardware_uuid.to_string(),
     };
-    println!("POST: {}", url);
-    println!("{:?}", body);
-    let response = client.post(&url).json(&body).send().await?;
+    debug!("POST: {}", url);
+    let response = AuthManager::client()?
+      .post(&url)
+      .json(&body)
+      .send()
+      .await
+      .map_err(|e| AuthManager::request_error(backend_hostname, e))?;
 
-    println!("{:?}", response.status());
+    match response.status() {
+      StatusCode::OK => Ok(response.json().await?),
+      StatusCode::CONFLICT => Err(AuthError::AlreadyRegistered),
+      StatusCode::BAD_REQUEST
+      | StatusCode::UNAUTHORIZED
+      | StatusCode::FORBIDDEN
+      | StatusCode::NOT_FOUND => Err(AuthError::InvalidKey(
+        AuthManager::error_message(response).await,
+      )),
+      status => Err(AuthError::Backend(
+        status,
+        AuthManager::error_message(response).await,
+      )),
+    }
+  }
+
+  /// Asks the backend whether the access token is still valid
+  #[cfg(feature = "token-endpoints")]
+  pub async fn status(
+    backend_hostname: &str,
+    access_token: &str,
+  ) -> Result<TokenStatus, AuthError> {
+    let response =
+      AuthManager::authorized_request(backend_hostname, access_token, "@status").await?;
 
     match response.status() {
-      reqwest::StatusCode::OK => {
-        let response_json: SignupResponse = serde_json::from_str(&response.text().await?)?;
-        Ok(response_json)
-      }
-      reqwest::StatusCode::BAD_REQUEST
-      | reqwest::StatusCode::NOT_FOUND
-      | reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
-        let response_json: SignupResponseError = serde_json::from_str(&response.text().await?)?;
-        Err(anyhow::anyhow!(response_json.error))
-      }
-      _any_other => {
-        println!("{:?}", _any_other);
-        return Err(anyhow::anyhow!("Unexpected response from Xornet"));
-      }
+      StatusCode::OK => Ok(TokenStatus::Valid),
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(TokenStatus::Invalid),
+      status => Err(AuthError::Backend(
+        status,
+        AuthManager::error_message(response).await,
+      )),
+    }
+  }
+
+  /// Exchanges the access token for a new one, the old token stops working
+  #[cfg(feature = "token-endpoints")]
+  pub async fn rotate(
+    backend_hostname: &str,
+    access_token: &str,
+  ) -> Result<SignupResponse, AuthError> {
+    let response =
+      AuthManager::authorized_request(backend_hostname, access_token, "@rotate").await?;
+
+    match response.status() {
+      StatusCode::OK => Ok(response.json().await?),
+      status => Err(AuthManager::token_error(status, response).await),
+    }
+  }
+
+  /// Revokes the access token on the backend
+  #[cfg(feature = "token-endpoints")]
+  pub async fn revoke(backend_hostname: &str, access_token: &str) -> Result<(), AuthError> {
+    let response =
+      AuthManager::authorized_request(backend_hostname, access_token, "@revoke").await?;
+
+    match response.status() {
+      StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+      status => Err(AuthManager::token_error(status, response).await),
+    }
+  }
+
+  fn client() -> Result<reqwest::Client, AuthError> {
+    Ok(
+      reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?,
+    )
+  }
+
+  /// Sends a POST to one of the machine endpoints authorized with the access token
+  #[cfg(feature = "token-endpoints")]
+  async fn authorized_request(
+    backend_hostname: &str,
+    access_token: &str,
+    endpoint: &str,
+  ) -> Result<Response, AuthError> {
+    if access_token.is_empty() {
+      return Err(AuthError::NotSignedUp);
+    }
+
+    let url = format!("https://{}/machines/{}", backend_hostname, endpoint);
+    debug!("POST: {}", url);
+    AuthManager::client()?
+      .post(&url)
+      .bearer_auth(access_token)
+      .send()
+      .await
+      .map_err(|e| AuthManager::request_error(backend_hostname, e))
+  }
+
+  /// Tells apart a backend that couldn't be reached from other request failures
+  fn request_error(backend_hostname: &str, error: reqwest::Error) -> AuthError {
+    if error.is_connect() || error.is_timeout() {
+      AuthError::Unreachable(backend_hostname.to_string(), error)
+    } else {
+      AuthError::Request(error)
+    }
+  }
+
+  #[cfg(feature = "token-endpoints")]
+  async fn token_error(status: StatusCode, response: Response) -> AuthError {
+    match status {
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AuthError::InvalidToken,
+      status => AuthError::Backend(status, AuthManager::error_message(response).await),
+    }
+  }
+
+  /// Gets the error the backend sent or the raw body if it isn't in the usual format
+  async fn error_message(response: Response) -> String {
+    let text = response.text().await.unwrap_or_default();
+    match serde_json::from_str::<SignupResponseError>(&text) {
+      Ok(response_json) => response_json.error,
+      Err(_) => text,
     }
   }
 }
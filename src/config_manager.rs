@@ -1,65 +1,1045 @@
+use crate::filter::{compile_glob, CompiledFilters, Filter};
+use crate::logger;
 use anyhow::Result;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::path::Path;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
 use uuid::Uuid;
 
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+pub const CONFIG_FILE: &str = "config.json";
+pub const CONFIG_BACKUP_FILE: &str = "config.json.bak";
+pub const SECRETS_FILE: &str = "secrets.json";
+pub const DEFAULT_BACKEND_HOSTNAME: &str = "xbackend.otiskujawa.net";
+
+/// The version of the config layout this reporter writes
+pub const CONFIG_VERSION: u64 = 4;
+
+/// Migrations that upgrade a config from version `index` to `index + 1`
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[
+  migrate_v0_to_v1,
+  migrate_v1_to_v2,
+  migrate_v2_to_v3,
+  migrate_v3_to_v4,
+];
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+  #[error("config.json is not valid JSON: {0}. Fix or remove the file and retry")]
+  Corrupt(serde_json::Error),
+  #[error("config.json must contain a JSON object at the top level")]
+  NotAnObject,
+  #[error("config.json has an invalid \"version\" field, expected a positive integer")]
+  InvalidVersion,
+  #[error("config.json is version {0} but this reporter only supports up to version {1}, please update the reporter")]
+  UnsupportedVersion(u64, u64),
+  #[error("config.json does not match the expected layout: {0}")]
+  Schema(serde_json::Error),
+  #[error("Invalid \"{field}\" in config.json: {reason}")]
+  InvalidField { field: &'static str, reason: String },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+  pub version: u64,
+  /// Kept in the secrets file, this is only read from config.json
+  /// to move it out of configs that predate the secrets file
+  #[serde(default, skip_serializing)]
   pub access_token: String,
   pub backend_hostname: String,
   pub uuid: String,
+  pub collection: CollectionConfig,
+  pub log: LogConfig,
+}
+
+/// Settings for the reporter's own logs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+  /// The least severe level that gets logged, -v and -q move it up and down
+  pub level: logger::Level,
+  /// text, json or journald, overridden by --log-format
+  pub format: logger::Format,
+  /// Levels for specific modules like `websocket_manager` or `data_collector::nics`
+  pub modules: BTreeMap<String, logger::Level>,
+}
+
+impl Default for LogConfig {
+  fn default() -> Self {
+    Self {
+      level: logger::Level::Info,
+      format: logger::Format::Text,
+      modules: BTreeMap::new(),
+    }
+  }
+}
+
+/// Settings for what gets collected and how often
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectionConfig {
+  /// Data collection interval in seconds
+  pub interval: f64,
+  /// Disables sending data to Xornet's backend
+  pub offline: bool,
+  /// How many intervals pass before the network and disk lists
+  /// and the network link speeds are refreshed
+  pub program_iterations: usize,
+  pub collectors: CollectorsConfig,
+  pub intervals: IntervalsConfig,
+  pub timeouts: TimeoutsConfig,
+  pub filters: FiltersConfig,
+  pub top_processes: TopProcessesConfig,
+  pub pressure: PressureConfig,
+  pub cgroups: CgroupsConfig,
+  pub block_devices: BlockDevicesConfig,
+  pub network_disks: NetworkDisksConfig,
+  pub container: ContainerConfig,
+  pub roots: RootsConfig,
+  pub systemd: SystemdConfig,
+  /// External commands whose output is sent as custom metrics
+  pub exec: Vec<ExecCollectorConfig>,
+}
+
+impl Default for CollectionConfig {
+  fn default() -> Self {
+    Self {
+      interval: 1.0,
+      offline: false,
+      program_iterations: 60,
+      collectors: CollectorsConfig::default(),
+      intervals: IntervalsConfig::default(),
+      timeouts: TimeoutsConfig::default(),
+      filters: FiltersConfig::default(),
+      top_processes: TopProcessesConfig::default(),
+      pressure: PressureConfig::default(),
+      cgroups: CgroupsConfig::default(),
+      block_devices: BlockDevicesConfig::default(),
+      network_disks: NetworkDisksConfig::default(),
+      container: ContainerConfig::default(),
+      roots: RootsConfig::default(),
+      systemd: SystemdConfig::default(),
+      exec: Vec::new(),
+    }
+  }
+}
+
+/// Turns collectors on and off by their name in the registry, collectors that aren't in it
+/// run unless they're off by default, like top_processes and systemd. A disabled collector
+/// is sent as null.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct CollectorsConfig {
+  /// The reporter's own stats like collection and send times
+  pub reporter: bool,
+  #[serde(flatten)]
+  pub collectors: BTreeMap<String, bool>,
+}
+
+impl CollectorsConfig {
+  /// Whether the collector of that name should run, `default` being the collector's own
+  pub fn enabled(&self, collector: &str, default: bool) -> bool {
+    self.collectors.get(collector).copied().unwrap_or(default)
+  }
+}
+
+/// Intervals in seconds by collector name for collectors that shouldn't run on every tick,
+/// collectors without one run at their own interval or every `interval`. Runs are aligned to
+/// the wall clock, so a 30s interval runs at :00 and :30 of every minute, and the last value
+/// is sent in between.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct IntervalsConfig {
+  /// How often the static data like the public IP is sent again
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub statics: Option<f64>,
+  #[serde(flatten)]
+  pub collectors: BTreeMap<String, f64>,
+}
+
+impl Default for IntervalsConfig {
+  fn default() -> Self {
+    Self {
+      statics: Some(3600.0),
+      collectors: BTreeMap::new(),
+    }
+  }
+}
+
+impl IntervalsConfig {
+  /// The interval the config sets for the collector of that name, if any
+  pub fn get(&self, collector: &str) -> Option<f64> {
+    self.collectors.get(collector).copied()
+  }
+}
+
+/// How many seconds each collector gets before the sample is sent without it, by collector
+/// name. Collectors without a timeout of their own get the default one.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+  pub default: f64,
+  /// Applies to looking up the public IP and location
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub statics: Option<f64>,
+  #[serde(flatten)]
+  pub collectors: BTreeMap<String, f64>,
+}
+
+impl Default for TimeoutsConfig {
+  fn default() -> Self {
+    Self {
+      default: 5.0,
+      statics: Some(15.0),
+      collectors: BTreeMap::new(),
+    }
+  }
+}
+
+impl TimeoutsConfig {
+  /// The timeout the config sets for the collector of that name, if any
+  pub fn get(&self, collector: &str) -> Option<f64> {
+    self.collectors.get(collector).copied()
+  }
+
+  /// The timeout of a collector, falling back to the default one
+  pub fn of(&self, timeout: Option<f64>) -> Duration {
+    Duration::from_secs_f64(timeout.unwrap_or(self.default))
+  }
+}
+
+/// Characters of regular expressions that can't be in a part of a cgroup path
+const CGROUP_PATH_REGEX_CHARACTERS: &str = "^$()[]{}|+";
+
+/// Whether a number of seconds like an interval or a timeout can be waited for
+pub fn is_positive_seconds(seconds: f64) -> bool {
+  seconds.is_finite() && seconds > 0.0
+}
+
+/// Checks a path under the cgroup v2 mount. Its parts are separated by slashes, so they can only
+/// be globs and not the `/regex/` of the filters, which would silently match nothing.
+fn validate_cgroup_path(path: &str) -> Result<(), String> {
+  if path.is_empty() {
+    return Err("paths must not be empty".to_string());
+  }
+
+  for part in path.split('/').filter(|part| !part.is_empty()) {
+    if part == "." || part == ".." {
+      return Err(format!("\"{}\" must not have . or .. in it", path));
+    }
+    if let Some(character) = part
+      .chars()
+      .find(|character| CGROUP_PATH_REGEX_CHARACTERS.contains(*character))
+    {
+      return Err(format!(
+        "\"{}\" has a '{}' in \"{}\", every part of a path is a glob with only * and ?",
+        path, character, part
+      ));
+    }
+    compile_glob(part).map_err(|e| format!("\"{}\" is not a valid glob: {}", part, e))?;
+  }
+  Ok(())
+}
+
+/// Keys of the collectors, intervals and timeouts maps that aren't collectors
+pub const RESERVED_COLLECTOR_NAMES: [&str; 3] = ["default", "reporter", "statics"];
+
+/// What the top processes are sorted by
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessSort {
+  #[default]
+  Cpu,
+  Memory,
+  /// Bytes read and written per second
+  DiskIo,
+}
+
+/// Settings for the table of the processes using the most resources
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopProcessesConfig {
+  /// How many processes are reported
+  pub count: usize,
+  pub sort_by: ProcessSort,
+  /// Whether command lines are reported at all
+  pub command_line: bool,
+  /// Characters of the command line that are kept, 0 keeps all of it
+  pub max_command_line: usize,
+  /// Regular expressions whose matches in command lines are replaced with `***`
+  pub redact: Vec<String>,
+}
+
+impl Default for TopProcessesConfig {
+  fn default() -> Self {
+    Self {
+      count: 10,
+      sort_by: ProcessSort::Cpu,
+      command_line: true,
+      max_command_line: 256,
+      redact: vec![r"(?i)(password|passwd|secret|token|api[_-]?key)[=: ]\S+".to_string()],
+    }
+  }
+}
+
+/// The top processes settings with the redaction patterns compiled
+#[derive(Clone, Debug)]
+pub struct CompiledTopProcesses {
+  pub count: usize,
+  pub sort_by: ProcessSort,
+  pub command_line: bool,
+  pub max_command_line: usize,
+  pub redact: Vec<Regex>,
+}
+
+impl TopProcessesConfig {
+  pub fn compile(&self) -> Result<CompiledTopProcesses, ConfigError> {
+    let redact = self
+      .redact
+      .iter()
+      .map(|pattern| Regex::new(pattern))
+      .collect::<Result<_, _>>()
+      .map_err(|e| ConfigError::InvalidField {
+        field: "collection.top_processes.redact",
+        reason: e.to_string(),
+      })?;
+
+    Ok(CompiledTopProcesses {
+      count: self.count,
+      sort_by: self.sort_by,
+      command_line: self.command_line,
+      max_command_line: self.max_command_line,
+      redact,
+    })
+  }
+}
+
+/// Settings for the pressure stall information
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PressureConfig {
+  /// Paths of cgroups under the cgroup v2 mount whose pressure is reported as well,
+  /// every part of the path can be a glob like `system.slice/*.service` but not a regex
+  pub cgroups: Vec<String>,
+}
+
+impl Default for PressureConfig {
+  fn default() -> Self {
+    Self {
+      cgroups: vec!["*.slice".to_string()],
+    }
+  }
+}
+
+/// Settings for the usage of the cgroup v2 groups
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CgroupsConfig {
+  /// Paths of the groups under the cgroup v2 mount, every part of the path can be
+  /// a glob but not a regex. The defaults cover systemd's slices and Docker and Podman containers.
+  pub paths: Vec<String>,
+}
+
+impl Default for CgroupsConfig {
+  fn default() -> Self {
+    Self {
+      paths: vec![
+        "*.slice".to_string(),
+        "*.slice/docker-*.scope".to_string(),
+        "*.slice/libpod-*.scope".to_string(),
+        "docker/*".to_string(),
+      ],
+    }
+  }
+}
+
+/// Settings for the I/O stats of the block devices
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockDevicesConfig {
+  /// Reports partitions on their own rather than only the disks they're on
+  pub partitions: bool,
+  pub loop_devices: bool,
+}
+
+/// Settings for when the reporter runs in a container
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContainerConfig {
+  /// Reports the CPU and memory usage and limits of the container's cgroup
+  /// rather than the host's, Linux only
+  pub scope_to_cgroup: bool,
+}
+
+/// Where procfs, sysfs and the root file system are read from on Linux, e.g. the host's
+/// mounted at /host/proc, /host/sys and /host to monitor it from a container
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RootsConfig {
+  pub proc: PathBuf,
+  pub sys: PathBuf,
+  /// Where the mount points and files like /.dockerenv are looked up
+  pub root: PathBuf,
+}
+
+impl Default for RootsConfig {
+  fn default() -> Self {
+    Self {
+      proc: PathBuf::from("/proc"),
+      sys: PathBuf::from("/sys"),
+      root: PathBuf::from("/"),
+    }
+  }
+}
+
+impl RootsConfig {
+  /// A path under procfs, like `stat` for /proc/stat
+  pub fn proc(&self, path: &str) -> PathBuf {
+    self.proc.join(path)
+  }
+
+  /// A path under sysfs, like `class/net` for /sys/class/net
+  pub fn sys(&self, path: &str) -> PathBuf {
+    self.sys.join(path)
+  }
+
+  /// An absolute path on the root file system, like `/home` for a mount point
+  pub fn root(&self, path: &str) -> PathBuf {
+    self.root.join(path.trim_start_matches('/'))
+  }
+
+  /// Whether procfs is the reporter's own rather than another one like the host's
+  pub fn is_own_proc(&self) -> bool {
+    self.proc == Self::default().proc
+  }
+
+  /// A file of the process whose mounts and cgroup are the ones reported, like `mountinfo`.
+  /// That's the reporter's own, or with another procfs like the host's the one of its init,
+  /// since the reporter's are the ones of the container it runs in.
+  pub fn process(&self, file: &str) -> String {
+    if self.is_own_proc() {
+      format!("self/{}", file)
+    } else {
+      format!("1/{}", file)
+    }
+  }
+}
+
+/// Settings for the health of the systemd units
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemdConfig {
+  /// Names of the units like `nginx.service`, or globs like `*.service` which
+  /// only match the units systemd has loaded
+  pub units: Vec<String>,
+}
+
+impl Default for SystemdConfig {
+  fn default() -> Self {
+    Self {
+      units: vec!["*.service".to_string()],
+    }
+  }
+}
+
+/// Settings for the network file systems
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkDisksConfig {
+  /// Seconds a file system gets to answer before it's reported as timed out,
+  /// a hung one isn't asked again until it answers
+  pub stat_timeout: f64,
+}
+
+impl Default for NetworkDisksConfig {
+  fn default() -> Self {
+    Self { stat_timeout: 2.0 }
+  }
+}
+
+/// How the output of an exec collector is read
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecFormat {
+  /// Any JSON value
+  #[default]
+  Json,
+  /// One `key=value` per line, numbers and booleans keep their type
+  KeyValue,
+}
+
+/// An external command that's run like a collector, its output ends up
+/// in the sample's `extra` map under its name
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ExecCollectorConfig {
+  pub name: String,
+  /// Path to the executable, it's run directly without a shell
+  pub command: String,
+  #[serde(default)]
+  pub args: Vec<String>,
+  #[serde(default)]
+  pub format: ExecFormat,
+  /// Seconds between runs, runs on every tick when not set
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub interval: Option<f64>,
+  /// Seconds before the command is killed, the default timeout when not set
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timeout: Option<f64>,
+}
+
+/// Filters that decide which disks, network interfaces and sensors get reported
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FiltersConfig {
+  /// Matched against the disk's device name
+  pub disks: Filter,
+  /// Matched against the disk's mount point
+  pub mounts: Filter,
+  /// Matched against the disk's file system
+  pub filesystems: Filter,
+  /// Matched against the network interface name
+  pub nics: Filter,
+  /// Matched against the temperature sensor label
+  pub temps: Filter,
+  /// Matched against the kernel's name of the block device like `sda` or `nvme0n1`
+  pub block_devices: Filter,
+}
+
+impl Default for FiltersConfig {
+  fn default() -> Self {
+    Self {
+      disks: Filter::exclude(&["*docker*"]),
+      mounts: Filter::exclude(&["*docker*", "*boot*"]),
+      filesystems: Filter::default(),
+      nics: Filter::exclude(&["*NPCAP*", "lo*"]),
+      temps: Filter::default(),
+      block_devices: Filter::exclude(&["ram*"]),
+    }
+  }
+}
+
+impl FiltersConfig {
+  /// Compiles every filter, failing on the first invalid pattern
+  pub fn compile(&self) -> Result<CompiledFilters, ConfigError> {
+    let compile = |field: &'static str, filter: &Filter| {
+      filter.compile().map_err(|e| ConfigError::InvalidField {
+        field,
+        reason: e.to_string(),
+      })
+    };
+
+    Ok(CompiledFilters {
+      disks: compile("collection.filters.disks", &self.disks)?,
+      mounts: compile("collection.filters.mounts", &self.mounts)?,
+      filesystems: compile("collection.filters.filesystems", &self.filesystems)?,
+      nics: compile("collection.filters.nics", &self.nics)?,
+      temps: compile("collection.filters.temps", &self.temps)?,
+      block_devices: compile("collection.filters.block_devices", &self.block_devices)?,
+    })
+  }
+}
+
+impl Config {
+  /// Checks every field of the config and returns the first problem found
+  pub fn validate(&self) -> Result<(), ConfigError> {
+    if self.version != CONFIG_VERSION {
+      return Err(ConfigError::UnsupportedVersion(
+        self.version,
+        CONFIG_VERSION,
+      ));
+    }
+
+    if self.backend_hostname.is_empty() {
+      return Err(ConfigError::InvalidField {
+        field: "backend_hostname",
+        reason: "must not be empty".to_string(),
+      });
+    }
+
+    if self.backend_hostname.contains("://")
+      || self.backend_hostname.contains('/')
+      || self.backend_hostname.contains(char::is_whitespace)
+    {
+      return Err(ConfigError::InvalidField {
+        field: "backend_hostname",
+        reason: format!(
+          "\"{}\" must be a bare hostname (with an optional port) without a scheme or path",
+          self.backend_hostname
+        ),
+      });
+    }
+
+    if Uuid::parse_str(&self.uuid).is_err() {
+      return Err(ConfigError::InvalidField {
+        field: "uuid",
+        reason: format!("\"{}\" is not a valid UUID", self.uuid),
+      });
+    }
+
+    if self.access_token.contains(char::is_whitespace) {
+      return Err(ConfigError::InvalidField {
+        field: "access_token",
+        reason: "must not contain whitespace".to_string(),
+      });
+    }
+
+    if !is_positive_seconds(self.collection.interval) {
+      return Err(ConfigError::InvalidField {
+        field: "collection.interval",
+        reason: format!(
+          "{} must be a positive number of seconds",
+          self.collection.interval
+        ),
+      });
+    }
+
+    if self.collection.program_iterations == 0 {
+      return Err(ConfigError::InvalidField {
+        field: "collection.program_iterations",
+        reason: "must be at least 1".to_string(),
+      });
+    }
+
+    let intervals = &self.collection.intervals;
+    let timeouts = &self.collection.timeouts;
+    let seconds = [
+      ("collection.intervals", "statics", intervals.statics),
+      ("collection.timeouts", "default", Some(timeouts.default)),
+      ("collection.timeouts", "statics", timeouts.statics),
+    ];
+    let collectors = (intervals
+      .collectors
+      .iter()
+      .map(|entry| ("collection.intervals", entry)))
+    .chain(
+      timeouts
+        .collectors
+        .iter()
+        .map(|entry| ("collection.timeouts", entry)),
+    )
+    .map(|(field, (name, &value))| (field, name.as_str(), Some(value)));
+    for (field, name, value) in seconds.iter().copied().chain(collectors) {
+      if let Some(value) = value {
+        if !is_positive_seconds(value) {
+          return Err(ConfigError::InvalidField {
+            field,
+            reason: format!(
+              "{} of \"{}\" must be a positive number of seconds",
+              value, name
+            ),
+          });
+        }
+      }
+    }
+
+    self.collection.filters.compile()?;
+    self.collection.top_processes.compile()?;
+
+    if self.collection.top_processes.count == 0 {
+      return Err(ConfigError::InvalidField {
+        field: "collection.top_processes.count",
+        reason: "must be at least 1".to_string(),
+      });
+    }
+
+    let pressure = &self.collection.pressure.cgroups;
+    let cgroups = &self.collection.cgroups.paths;
+    let cgroup_paths = (pressure
+      .iter()
+      .map(|path| ("collection.pressure.cgroups", path)))
+    .chain(
+      cgroups
+        .iter()
+        .map(|path| ("collection.cgroups.paths", path)),
+    );
+    for (field, path) in cgroup_paths {
+      validate_cgroup_path(path).map_err(|reason| ConfigError::InvalidField { field, reason })?;
+    }
+
+    let roots = &self.collection.roots;
+    let roots = [
+      ("collection.roots.proc", &roots.proc),
+      ("collection.roots.sys", &roots.sys),
+      ("collection.roots.root", &roots.root),
+    ];
+    for &(field, root) in roots.iter() {
+      if !root.is_absolute() {
+        return Err(ConfigError::InvalidField {
+          field,
+          reason: format!("{} must be an absolute path", root.display()),
+        });
+      }
+    }
+
+    let stat_timeout = self.collection.network_disks.stat_timeout;
+    if !is_positive_seconds(stat_timeout) {
+      return Err(ConfigError::InvalidField {
+        field: "collection.network_disks.stat_timeout",
+        reason: format!("{} must be a positive number of seconds", stat_timeout),
+      });
+    }
+
+    if self.collection.systemd.units.iter().any(String::is_empty) {
+      return Err(ConfigError::InvalidField {
+        field: "collection.systemd.units",
+        reason: "unit names must not be empty".to_string(),
+      });
+    }
+
+    for (index, exec) in self.collection.exec.iter().enumerate() {
+      if exec.name.is_empty() {
+        return Err(ConfigError::InvalidField {
+          field: "collection.exec",
+          reason: "names must not be empty".to_string(),
+        });
+      }
+
+      if RESERVED_COLLECTOR_NAMES.contains(&exec.name.as_str()) {
+        return Err(ConfigError::InvalidField {
+          field: "collection.exec",
+          reason: format!("\"{}\" is a reserved name", exec.name),
+        });
+      }
+
+      if self.collection.exec[..index]
+        .iter()
+        .any(|other| other.name == exec.name)
+      {
+        return Err(ConfigError::InvalidField {
+          field: "collection.exec",
+          reason: format!("\"{}\" is used by more than one collector", exec.name),
+        });
+      }
+
+      if exec.command.is_empty() {
+        return Err(ConfigError::InvalidField {
+          field: "collection.exec",
+          reason: format!("the command of \"{}\" must not be empty", exec.name),
+        });
+      }
+
+      for seconds in [exec.interval, exec.timeout].iter().flatten() {
+        if !is_positive_seconds(*seconds) {
+          return Err(ConfigError::InvalidField {
+            field: "collection.exec",
+            reason: format!(
+              "{} in \"{}\" must be a positive number of seconds",
+              seconds, exec.name
+            ),
+          });
+        }
+      }
+    }
+
+    if self.log.modules.keys().any(|module| module.is_empty()) {
+      return Err(ConfigError::InvalidField {
+        field: "log.modules",
+        reason: "module names must not be empty".to_string(),
+      });
+    }
+
+    Ok(())
+  }
+
+  /// Whether switching to `other` requires the websocket connection to be re-established
+  pub fn connection_changed(&self, other: &Config) -> bool {
+    self.backend_hostname != other.backend_hostname || self.access_token != other.access_token
+  }
+}
+
+/// Version 0 is the original unversioned layout which could be missing
+/// the uuid or backend hostname
+fn migrate_v0_to_v1(config: &mut Map<String, Value>) {
+  for field in ["access_token", "backend_hostname", "uuid"] {
+    if !matches!(config.get(field), Some(Value::String(_))) {
+      config.insert(field.to_string(), Value::String(String::new()));
+    }
+  }
+
+  if config["uuid"].as_str() == Some("") {
+    config.insert(
+      "uuid".to_string(),
+      Value::String(ConfigManager::create_uuid()),
+    );
+  }
+
+  if config["backend_hostname"].as_str() == Some("") {
+    config.insert(
+      "backend_hostname".to_string(),
+      Value::String(DEFAULT_BACKEND_HOSTNAME.to_string()),
+    );
+  }
+}
+
+/// Version 2 added the collection settings, which used to be hardcoded
+fn migrate_v1_to_v2(config: &mut Map<String, Value>) {
+  if !config.contains_key("collection") {
+    config.insert(
+      "collection".to_string(),
+      serde_json::to_value(CollectionConfig::default()).unwrap_or_default(),
+    );
+  }
+}
+
+/// Version 3 moved the access token into the secrets file,
+/// load_config moves the token itself since it needs to write the file
+fn migrate_v2_to_v3(_config: &mut Map<String, Value>) {}
+
+/// Version 4 added the log settings
+fn migrate_v3_to_v4(config: &mut Map<String, Value>) {
+  if !config.contains_key("log") {
+    config.insert(
+      "log".to_string(),
+      serde_json::to_value(LogConfig::default()).unwrap_or_default(),
+    );
+  }
+}
+
+/// Things that shouldn't be readable by anyone but the reporter,
+/// stored in secrets.json with 0600 permissions
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Secrets {
+  pub access_token: String,
 }
 
 /// Manages the config.json for the reporter
 #[derive(Clone, Debug)]
 pub struct ConfigManager {
   pub config: Config,
+  last_modified: [Option<SystemTime>; 2],
 }
 
 impl ConfigManager {
   pub fn new() -> Result<ConfigManager> {
     let config = ConfigManager::load_config()?;
-    Ok(Self { config })
+    Ok(Self {
+      config,
+      last_modified: ConfigManager::get_modified_times(),
+    })
+  }
+
+  /// Gets the last modification times of the config and secrets files if they exist
+  pub fn get_modified_times() -> [Option<SystemTime>; 2] {
+    [CONFIG_FILE, SECRETS_FILE].map(|path| {
+      fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+    })
+  }
+
+  /// Whether the config or secrets file has been modified since they were last loaded
+  pub fn has_changed_on_disk(&self) -> bool {
+    ConfigManager::get_modified_times() != self.last_modified
+  }
+
+  /// Reloads the config file from disk and returns the previous config.
+  ///
+  /// If the new file is missing or invalid the current config stays in place
+  /// and the error is returned instead.
+  pub fn reload(&mut self) -> Result<Config> {
+    // Remember this version of the file even if it's invalid so the same
+    // broken file isn't reported again on every check
+    self.last_modified = ConfigManager::get_modified_times();
+
+    if !Path::new(CONFIG_FILE).exists() {
+      return Err(anyhow::anyhow!("{} was removed", CONFIG_FILE));
+    }
+
+    let config = ConfigManager::load_config()?;
+
+    // Loading can rewrite the file when it gets migrated
+    self.last_modified = ConfigManager::get_modified_times();
+
+    Ok(std::mem::replace(&mut self.config, config))
   }
 
   pub fn save_access_token(access_token: &str) -> Result<()> {
-    let mut config = ConfigManager::load_config()?;
-    config.access_token = access_token.to_string();
-    ConfigManager::save_config(config)?;
+    // Make sure there's a valid config to go with the token
+    ConfigManager::load_config()?;
+    ConfigManager::save_secrets(&Secrets {
+      access_token: access_token.to_string(),
+    })
+  }
+
+  /// Forgets the access token by removing the secrets file and
+  /// any copy of it left in the config backups
+  pub fn remove_access_token() -> Result<()> {
+    if Path::new(SECRETS_FILE).exists() {
+      fs::remove_file(SECRETS_FILE)?;
+    }
+    ConfigManager::scrub_backups()
+  }
+
+  /// Loads the secrets file, an empty set of secrets is returned if it doesn't exist
+  pub fn load_secrets() -> Result<Secrets> {
+    if !Path::new(SECRETS_FILE).exists() {
+      return Ok(Secrets::default());
+    }
+
+    let file = File::open(SECRETS_FILE)?;
+    serde_json::from_reader(file).map_err(|e| {
+      anyhow::anyhow!(
+        "{} is invalid: {}. Fix or remove the file and retry",
+        SECRETS_FILE,
+        e
+      )
+    })
+  }
+
+  /// Saves the secrets file so only the owner can read it
+  pub fn save_secrets(secrets: &Secrets) -> Result<()> {
+    ConfigManager::write_private(SECRETS_FILE, &serde_json::to_vec_pretty(secrets)?)
+  }
+
+  /// Writes a file that only its owner can read
+  fn write_private(path: &str, contents: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(target_family = "unix")]
+    options.mode(0o600);
+
+    let mut file = options.open(path)?;
+    // The mode only applies to new files so tighten existing ones too
+    ConfigManager::restrict_permissions(path)?;
+    file.write_all(contents)?;
+    Ok(())
+  }
+
+  /// Makes the file readable and writable by its owner only
+  #[cfg(target_family = "unix")]
+  fn restrict_permissions(path: &str) -> Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
     Ok(())
   }
 
-  /// Saves the modified config to the config file
+  #[cfg(target_family = "windows")]
+  fn restrict_permissions(_path: &str) -> Result<()> {
+    Ok(())
+  }
+
+  /// Validates and saves the modified config to the config file,
+  /// backing up the previous file first
   pub fn save_config(config: Config) -> Result<()> {
-    let file = File::create("config.json")?;
+    config.validate()?;
+    ConfigManager::backup_config()?;
+    let file = File::create(CONFIG_FILE)?;
     serde_json::to_writer_pretty(file, &config)?;
     Ok(())
   }
 
+  /// Copies the current config file next to itself so a rewrite can always be undone
+  pub fn backup_config() -> Result<()> {
+    if Path::new(CONFIG_FILE).exists() {
+      ConfigManager::write_backup(CONFIG_FILE, CONFIG_BACKUP_FILE)?;
+    }
+    Ok(())
+  }
+
+  /// Copies a config file without the access token older layouts kept in it,
+  /// which lives in the secrets file instead. A file that isn't a JSON object
+  /// is copied as it is, the backups are private either way.
+  fn write_backup(source: &str, backup: &str) -> Result<()> {
+    let contents = fs::read(source)?;
+    let contents = match serde_json::from_slice::<Value>(&contents) {
+      Ok(Value::Object(mut object)) => {
+        object.remove("access_token");
+        serde_json::to_vec_pretty(&object)?
+      }
+      _ => contents,
+    };
+    ConfigManager::write_private(backup, &contents)
+  }
+
+  /// Strips the access token from the backups, which older reporters copied as they were
+  fn scrub_backups() -> Result<()> {
+    for entry in fs::read_dir(".")? {
+      let name = entry?.file_name().to_string_lossy().to_string();
+      let is_backup = name == CONFIG_BACKUP_FILE
+        || (name.starts_with(&format!("{}.v", CONFIG_FILE)) && name.ends_with(".bak"));
+      if is_backup {
+        ConfigManager::write_backup(&name, &name)?;
+      }
+    }
+    Ok(())
+  }
+
   /// Loads the config file from disk or creates a new one if it doesn't exist.
+  ///
+  /// Older layouts are migrated to the current version and written back,
+  /// a file that can't be parsed or validated is left untouched and an error is returned.
   pub fn load_config() -> Result<Config> {
-    if !Path::new("config.json").exists() {
-      Ok(ConfigManager::create_config()?)
+    if !Path::new(CONFIG_FILE).exists() {
+      return ConfigManager::create_config();
+    }
+
+    let contents = fs::read_to_string(CONFIG_FILE)?;
+    let (mut config, previous_version) = ConfigManager::parse_config(&contents)?;
+    let mut needs_saving = previous_version != CONFIG_VERSION;
+
+    // Keep the pre-migration file around under its own name so later saves
+    // that overwrite the regular backup can't lose the original layout
+    if needs_saving {
+      let backup = format!("{}.v{}.bak", CONFIG_FILE, previous_version);
+      ConfigManager::write_backup(CONFIG_FILE, &backup)?;
+    }
+
+    if config.access_token.is_empty() {
+      config.access_token = ConfigManager::load_secrets()?.access_token;
     } else {
-      let file = File::open("config.json")?;
-
-      let result = serde_json::from_reader(file);
-      match result {
-        Ok(config) => {
-          let mut config: Config = config;
-          if config.uuid.is_empty() {
-            config.uuid = ConfigManager::create_uuid();
-          }
-          if config.backend_hostname.is_empty() {
-            config.uuid = "xbackend.otiskujawa.net".to_string();
-          }
-          ConfigManager::save_config(config.clone())?;
-          Ok(config)
-        }
-        Err(_) => Ok(ConfigManager::create_config()?),
-      }
+      // A token in config.json is moved into the secrets file
+      ConfigManager::save_secrets(&Secrets {
+        access_token: config.access_token.clone(),
+      })?;
+      ConfigManager::scrub_backups()?;
+      needs_saving = true;
+    }
+
+    if needs_saving {
+      ConfigManager::save_config(config.clone())?;
+    }
+
+    Ok(config)
+  }
+
+  /// Parses, migrates and validates the contents of a config file.
+  /// Returns the config and the version the file was migrated from.
+  pub fn parse_config(contents: &str) -> Result<(Config, u64), ConfigError> {
+    let value: Value = serde_json::from_str(contents).map_err(ConfigError::Corrupt)?;
+    let mut object = match value {
+      Value::Object(object) => object,
+      _ => return Err(ConfigError::NotAnObject),
+    };
+
+    let version = match object.get("version") {
+      None => 0,
+      Some(version) => version.as_u64().ok_or(ConfigError::InvalidVersion)?,
+    };
+
+    if version > CONFIG_VERSION {
+      return Err(ConfigError::UnsupportedVersion(version, CONFIG_VERSION));
     }
+
+    for migration in &MIGRATIONS[version as usize..] {
+      migration(&mut object);
+    }
+    object.insert("version".to_string(), Value::from(CONFIG_VERSION));
+
+    let config: Config =
+      serde_json::from_value(Value::Object(object)).map_err(ConfigError::Schema)?;
+    config.validate()?;
+
+    Ok((config, version))
   }
 
   pub fn create_uuid() -> String {
@@ -69,9 +1049,12 @@ impl ConfigManager {
   /// Creates a new config file with an empty access token and default backend address.
   pub fn create_config() -> Result<Config> {
     let config = Config {
+      version: CONFIG_VERSION,
       access_token: String::new(),
-      backend_hostname: "xbackend.otiskujawa.net".to_string(),
+      backend_hostname: DEFAULT_BACKEND_HOSTNAME.to_string(),
       uuid: ConfigManager::create_uuid(),
+      collection: CollectionConfig::default(),
+      log: LogConfig::default(),
     };
     ConfigManager::save_config(config.clone())?;
     Ok(config)
@@ -84,3 +1067,113 @@ impl ConfigManager {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn parse(collection: Value) -> Result<(Config, u64), ConfigError> {
+    let config = json!({
+      "version": CONFIG_VERSION,
+      "backend_hostname": DEFAULT_BACKEND_HOSTNAME,
+      "uuid": "5b8ee6e8-3e4b-4a2e-9f4b-9c2a0f5f8e1d",
+      "collection": collection,
+      "log": {},
+    });
+    ConfigManager::parse_config(&config.to_string())
+  }
+
+  #[test]
+  fn keys_collector_settings_by_name() {
+    let (config, _) = parse(json!({
+      "collectors": { "reporter": true, "cpu": false, "backups": true },
+      "intervals": { "disks": 60 },
+      "timeouts": { "default": 2, "backups": 10 },
+    }))
+    .unwrap();
+
+    let collection = config.collection;
+    assert!(collection.collectors.reporter);
+    assert!(!collection.collectors.enabled("cpu", true));
+    assert!(collection.collectors.enabled("backups", false));
+    assert!(!collection.collectors.enabled("systemd", false));
+    assert_eq!(collection.intervals.get("disks"), Some(60.0));
+    assert_eq!(collection.intervals.get("cpu"), None);
+    assert_eq!(collection.intervals.statics, Some(3600.0));
+    assert_eq!(collection.timeouts.get("backups"), Some(10.0));
+    assert_eq!(collection.timeouts.of(None), Duration::from_secs(2));
+  }
+
+  #[test]
+  fn rejects_cgroup_paths_that_arent_globs() {
+    let (config, _) = parse(json!({
+      "pressure": { "cgroups": ["system.slice/*.service", "/"] },
+      "cgroups": { "paths": ["*.slice/docker-*.scope", "system.slice/systemd-fsck@dev-vda\\x2d1.service"] },
+    }))
+    .unwrap();
+    assert_eq!(config.collection.pressure.cgroups.len(), 2);
+
+    for (collection, expected) in [
+      (
+        json!({ "pressure": { "cgroups": ["/^system/"] } }),
+        "collection.pressure.cgroups",
+      ),
+      (
+        json!({ "cgroups": { "paths": ["docker/(abc|def)"] } }),
+        "collection.cgroups.paths",
+      ),
+      (
+        json!({ "cgroups": { "paths": ["../*"] } }),
+        "collection.cgroups.paths",
+      ),
+      (
+        json!({ "cgroups": { "paths": [""] } }),
+        "collection.cgroups.paths",
+      ),
+    ] {
+      assert!(matches!(
+        parse(collection),
+        Err(ConfigError::InvalidField { field, .. }) if field == expected
+      ));
+    }
+  }
+
+  #[test]
+  fn rejects_intervals_and_timeouts_that_arent_positive() {
+    for collection in [
+      json!({ "intervals": { "disks": 0 } }),
+      json!({ "timeouts": { "cpu": -1 } }),
+      json!({ "timeouts": { "default": 0 } }),
+    ] {
+      assert!(matches!(
+        parse(collection),
+        Err(ConfigError::InvalidField { field, .. }) if field.starts_with("collection.")
+      ));
+    }
+  }
+
+  #[test]
+  fn reads_the_hosts_process_with_its_roots() {
+    let (config, _) = parse(json!({})).unwrap();
+    let roots = config.collection.roots;
+    assert_eq!(roots.process("mountinfo"), "self/mountinfo");
+    assert_eq!(roots.root("/.dockerenv"), PathBuf::from("/.dockerenv"));
+
+    let (config, _) = parse(json!({
+      "roots": { "proc": "/host/proc", "sys": "/host/sys", "root": "/host" },
+    }))
+    .unwrap();
+    let roots = config.collection.roots;
+    assert_eq!(roots.process("cgroup"), "1/cgroup");
+    assert_eq!(roots.root("/mnt/data"), PathBuf::from("/host/mnt/data"));
+
+    assert!(matches!(
+      parse(json!({ "roots": { "root": "host" } })),
+      Err(ConfigError::InvalidField {
+        field: "collection.roots.root",
+        ..
+      })
+    ));
+  }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::config_manager::{BlockDevicesConfig, RootsConfig};
+use crate::filter::CompiledFilters;
+use crate::types::BlockDeviceStats;
+
+use super::collector::{CollectContext, Collector};
+
+#[cfg(target_os = "linux")]
+use super::procfs::{self, CounterHistory, DiskCounters, DiskStat};
+
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+
+#[cfg(not(target_os = "linux"))]
+use super::DataCollectorError;
+
+/// /proc/diskstats counts sectors of 512 bytes no matter the device's actual sector size
+#[cfg(target_os = "linux")]
+const SECTOR_SIZE: f64 = 512.0;
+
+#[derive(Debug, Default)]
+pub struct BlockDeviceCollector {
+  /// The counters of every device
+  #[cfg(target_os = "linux")]
+  history: CounterHistory<HashMap<String, DiskCounters>>,
+}
+
+impl BlockDeviceCollector {
+  /// Gets the I/O of every block device since the last run
+  #[cfg(target_os = "linux")]
+  pub fn get_block_devices(
+    &mut self,
+    filters: &CompiledFilters,
+    config: &BlockDevicesConfig,
+    roots: &RootsConfig,
+  ) -> Result<Vec<BlockDeviceStats>> {
+    let devices = procfs::read_diskstats(roots)?;
+    let mounts = procfs::mounts_by_device(roots)?;
+    let delta = self.history.start();
+
+    // Partitions that aren't reported on their own add their mounts to their disk
+    let mut reported = Vec::new();
+    let mut device_mounts: HashMap<String, Vec<String>> = HashMap::new();
+    for device in &devices {
+      let owner = match procfs::partition_parent(roots, &device.name) {
+        Some(parent) if !config.partitions => parent,
+        _ => {
+          reported.push(device);
+          device.name.clone()
+        }
+      };
+      if let Some(mounts) = mounts.get(&device.device) {
+        device_mounts
+          .entry(owner)
+          .or_default()
+          .extend(mounts.iter().cloned());
+      }
+    }
+
+    let mut block_devices = Vec::new();
+    for DiskStat { name, counters, .. } in reported {
+      if (!config.loop_devices && name.starts_with("loop")) || !filters.block_devices.allows(name) {
+        continue;
+      }
+
+      let previous = delta.last_of(name, counters);
+      let difference = |now: u64, last: u64| now.saturating_sub(last) as f64;
+      let per_second = |amount: f64| delta.per_second(amount);
+      let average = |ms: f64, ios: f64| if ios > 0.0 { ms / ios } else { 0.0 };
+      // Milliseconds spent per second of time
+      let busy = |ms: f64| delta.per_second(ms) / 1000.0;
+
+      let reads = difference(counters.reads, previous.reads);
+      let writes = difference(counters.writes, previous.writes);
+      block_devices.push(BlockDeviceStats {
+        name: name.clone(),
+        mounts: device_mounts.remove(name).unwrap_or_default(),
+        read_bytes: per_second(
+          difference(counters.sectors_read, previous.sectors_read) * SECTOR_SIZE,
+        ),
+        write_bytes: per_second(
+          difference(counters.sectors_written, previous.sectors_written) * SECTOR_SIZE,
+        ),
+        read_iops: per_second(reads),
+        write_iops: per_second(writes),
+        read_await: average(difference(counters.read_ms, previous.read_ms), reads),
+        write_await: average(difference(counters.write_ms, previous.write_ms), writes),
+        queue_depth: busy(difference(counters.weighted_io_ms, previous.weighted_io_ms)),
+        utilization: (busy(difference(counters.io_ms, previous.io_ms)) * 100.0).min(100.0),
+        in_flight: counters.in_flight,
+      });
+    }
+
+    let counters = devices
+      .into_iter()
+      .map(|device| (device.name, device.counters))
+      .collect();
+    self.history.finish(counters);
+    Ok(block_devices)
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  pub fn get_block_devices(
+    &mut self,
+    _filters: &CompiledFilters,
+    _config: &BlockDevicesConfig,
+    _roots: &RootsConfig,
+  ) -> Result<Vec<BlockDeviceStats>> {
+    Err(DataCollectorError::Unsupported.into())
+  }
+}
+
+impl Collector for BlockDeviceCollector {
+  fn name(&self) -> &str {
+    "block_devices"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let collection = &context.collection;
+    let block_devices = self.get_block_devices(
+      &context.filters,
+      &collection.block_devices,
+      &collection.roots,
+    )?;
+    Ok(serde_json::to_value(block_devices)?)
+  }
+}
@@ -0,0 +1,317 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::config_manager::RootsConfig;
+use crate::types::CgroupStats;
+
+use super::collector::{CollectContext, Collector};
+use super::DataCollectorError;
+
+#[cfg(target_os = "linux")]
+use super::procfs::{self, CounterHistory};
+
+#[cfg(target_os = "linux")]
+use crate::types::ContainerInfo;
+
+#[cfg(target_os = "linux")]
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fs,
+  path::Path,
+};
+
+/// The counters of a group that are turned into rates, all of them since it was created
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Default)]
+struct CgroupCounters {
+  usage_usec: u64,
+  periods: u64,
+  throttled_periods: u64,
+  throttled_usec: u64,
+  io_read: u64,
+  io_write: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct CgroupCollector {
+  /// The counters of every group by path
+  #[cfg(target_os = "linux")]
+  history: CounterHistory<HashMap<String, CgroupCounters>>,
+  /// What the runtimes had to say about the containers, read once per container
+  /// unless nothing was found, in which case it's read again on the next collection
+  #[cfg(target_os = "linux")]
+  containers: HashMap<String, ContainerInfo>,
+}
+
+/// Reads a JSON file, None when it's missing or unreadable
+#[cfg(target_os = "linux")]
+fn read_json(path: &Path) -> Option<Value> {
+  serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// The string values of a JSON object like Docker's labels or OCI annotations
+#[cfg(target_os = "linux")]
+fn string_map(value: Option<&Value>) -> BTreeMap<String, String> {
+  value
+    .and_then(Value::as_object)
+    .map(|object| {
+      object
+        .iter()
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Describes a container from the metadata files its runtime keeps on the root file system,
+/// which usually only root can read
+#[cfg(target_os = "linux")]
+fn read_container(roots: &RootsConfig, runtime: &str, id: &str) -> ContainerInfo {
+  let mut container = ContainerInfo {
+    id: id.to_string(),
+    runtime: runtime.to_string(),
+    name: None,
+    image: None,
+    labels: BTreeMap::new(),
+  };
+
+  match runtime {
+    "docker" => {
+      let config =
+        read_json(&roots.root(&format!("/var/lib/docker/containers/{}/config.v2.json", id)));
+      if let Some(config) = config {
+        let name = config["Name"]
+          .as_str()
+          .map(|name| name.trim_start_matches('/'));
+        container.name = name.map(String::from);
+        container.image = config["Config"]["Image"].as_str().map(String::from);
+        container.labels = string_map(config.get("Config").and_then(|config| config.get("Labels")));
+      }
+    }
+    "podman" => {
+      let containers =
+        read_json(&roots.root("/var/lib/containers/storage/overlay-containers/containers.json"));
+      let found = containers
+        .as_ref()
+        .and_then(Value::as_array)
+        .and_then(|containers| containers.iter().find(|container| container["id"] == id));
+      if let Some(found) = found {
+        container.name = found["names"][0].as_str().map(String::from);
+        // The image is an id, its name is in the metadata which is JSON in a string
+        let metadata = found["metadata"]
+          .as_str()
+          .and_then(|metadata| serde_json::from_str::<Value>(metadata).ok());
+        container.image = metadata
+          .as_ref()
+          .and_then(|metadata| metadata["image-name"].as_str())
+          .or_else(|| found["image"].as_str())
+          .map(String::from);
+      }
+    }
+    // Both keep the OCI spec of the container, with the Kubernetes names in its annotations
+    "containerd" | "cri-o" => {
+      let (spec, name, image) = if runtime == "containerd" {
+        (
+          format!(
+            "/run/containerd/io.containerd.runtime.v2.task/k8s.io/{}/config.json",
+            id
+          ),
+          "io.kubernetes.cri.container-name",
+          "io.kubernetes.cri.image-name",
+        )
+      } else {
+        (
+          format!(
+            "/run/containers/storage/overlay-containers/{}/userdata/config.json",
+            id
+          ),
+          "io.kubernetes.container.name",
+          "io.kubernetes.cri-o.ImageName",
+        )
+      };
+      if let Some(spec) = read_json(&roots.root(&spec)) {
+        let annotations = string_map(spec.get("annotations"));
+        container.name = annotations.get(name).cloned();
+        container.image = annotations.get(image).cloned();
+        container.labels = annotations;
+      }
+    }
+    _ => {}
+  }
+
+  container
+}
+
+/// The bytes read and written by a group on every device together, from its io.stat
+#[cfg(target_os = "linux")]
+fn read_io(path: &Path) -> Option<(u64, u64)> {
+  let contents = fs::read_to_string(path.join("io.stat")).ok()?;
+  let mut io = (0, 0);
+  for field in contents.split_whitespace() {
+    match field.split_once('=') {
+      Some(("rbytes", value)) => io.0 += value.parse().unwrap_or(0),
+      Some(("wbytes", value)) => io.1 += value.parse().unwrap_or(0),
+      _ => {}
+    }
+  }
+  Some(io)
+}
+
+impl CgroupCollector {
+  /// Gets the usage of the groups matching the paths since the last run
+  #[cfg(target_os = "linux")]
+  pub fn get_cgroups(&mut self, paths: &[String], roots: &RootsConfig) -> Result<Vec<CgroupStats>> {
+    let root = procfs::cgroup2_root(roots).ok_or(DataCollectorError::Unsupported)?;
+    let delta = self.history.start();
+
+    // A group can match more than one pattern but is only reported once
+    let mut seen = HashSet::new();
+    let mut counters = HashMap::new();
+    let mut cgroups = Vec::new();
+    for pattern in paths {
+      for (name, path) in procfs::find_cgroups(&root, pattern) {
+        if !seen.insert(name.clone()) {
+          continue;
+        }
+
+        let cpu = procfs::read_cgroup_values(&path.join("cpu.stat")).unwrap_or_default();
+        let cpu_counter = |key: &str| cpu.get(key).copied().unwrap_or(0);
+        let io = read_io(&path);
+        let current = CgroupCounters {
+          usage_usec: cpu_counter("usage_usec"),
+          periods: cpu_counter("nr_periods"),
+          throttled_periods: cpu_counter("nr_throttled"),
+          throttled_usec: cpu_counter("throttled_usec"),
+          io_read: io.map_or(0, |io| io.0),
+          io_write: io.map_or(0, |io| io.1),
+        };
+        let previous = delta.last_of(&name, &current);
+        let periods = current.periods.saturating_sub(previous.periods);
+        let throttled = if periods > 0 {
+          current
+            .throttled_periods
+            .saturating_sub(previous.throttled_periods) as f32
+            / periods as f32
+        } else {
+          0.0
+        };
+        let container =
+          procfs::container_id(&name).map(|(runtime, id)| self.container(roots, runtime, id));
+
+        cgroups.push(CgroupStats {
+          container,
+          cpu_usage: (delta.rate(current.usage_usec, previous.usage_usec) / 10_000.0) as f32,
+          cpu_limit: procfs::read_cgroup_cpu_limit(&path),
+          throttled: throttled * 100.0,
+          throttled_time: (delta.rate(current.throttled_usec, previous.throttled_usec)
+            / 1_000_000.0) as f32,
+          // cgroups count bytes while RAMStats and the processes are in KB
+          memory: procfs::read_cgroup_value(&path.join("memory.current")).map(|bytes| bytes / 1000),
+          memory_max: procfs::read_cgroup_value(&path.join("memory.max")).map(|bytes| bytes / 1000),
+          io_read: io.map(|_| delta.rate(current.io_read, previous.io_read)),
+          io_write: io.map(|_| delta.rate(current.io_write, previous.io_write)),
+          pids: procfs::read_cgroup_value(&path.join("pids.current")),
+          pids_max: procfs::read_cgroup_value(&path.join("pids.max")),
+          path: name.clone(),
+        });
+        counters.insert(name, current);
+      }
+    }
+
+    // Containers that are gone are forgotten so a reused id gets read again
+    let ids: HashSet<_> = cgroups
+      .iter()
+      .filter_map(|cgroup| cgroup.container.as_ref())
+      .map(|container| container.id.clone())
+      .collect();
+    self.containers.retain(|id, _| ids.contains(id));
+
+    self.history.finish(counters);
+    Ok(cgroups)
+  }
+
+  /// The metadata of a container, cached once the runtime's files told its name or image.
+  /// They can be missing for a moment after the container starts, so a lookup that found
+  /// nothing is tried again instead of leaving the container unnamed until it's gone
+  #[cfg(target_os = "linux")]
+  fn container(&mut self, roots: &RootsConfig, runtime: &str, id: &str) -> ContainerInfo {
+    if let Some(container) = self.containers.get(id) {
+      return container.clone();
+    }
+
+    let container = read_container(roots, runtime, id);
+    if container.name.is_some() || container.image.is_some() {
+      self.containers.insert(id.to_string(), container.clone());
+    }
+    container
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  pub fn get_cgroups(
+    &mut self,
+    _paths: &[String],
+    _roots: &RootsConfig,
+  ) -> Result<Vec<CgroupStats>> {
+    Err(DataCollectorError::Unsupported.into())
+  }
+}
+
+impl Collector for CgroupCollector {
+  fn name(&self) -> &str {
+    "cgroups"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let collection = &context.collection;
+    let cgroups = self.get_cgroups(&collection.cgroups.paths, &collection.roots)?;
+    Ok(serde_json::to_value(cgroups)?)
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  const ID: &str = "3f4a9c1e5b7d2a8f6e0c4b9a1d3e5f7a9b2c4d6e8f0a1b3c5d7e9f1a2b3c4d5e";
+
+  /// The roots of the fixture tree of a host, whose runtimes keep their metadata under it
+  fn roots() -> RootsConfig {
+    let host = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/host");
+    RootsConfig {
+      proc: host.join("proc"),
+      sys: host.join("sys"),
+      root: host,
+    }
+  }
+
+  #[test]
+  fn reads_container_metadata_under_the_root() {
+    let docker = read_container(&roots(), "docker", ID);
+    assert_eq!(docker.name.as_deref(), Some("web"));
+    assert_eq!(docker.image.as_deref(), Some("nginx:1.25"));
+    assert_eq!(docker.labels["com.docker.compose.service"], "web");
+
+    let containerd = read_container(&roots(), "containerd", ID);
+    assert_eq!(containerd.name.as_deref(), Some("api"));
+    assert_eq!(containerd.image.as_deref(), Some("registry.local/api:2"));
+
+    // The host's reporter can't tell anything about a container whose files it can't find
+    let podman = read_container(&roots(), "podman", ID);
+    assert_eq!((podman.name, podman.image), (None, None));
+    assert!(podman.labels.is_empty());
+  }
+
+  #[test]
+  fn only_caches_containers_that_were_found() {
+    let mut collector = CgroupCollector::default();
+    assert_eq!(
+      collector.container(&roots(), "docker", ID).name.as_deref(),
+      Some("web")
+    );
+    assert!(collector.containers.contains_key(ID));
+
+    let mut collector = CgroupCollector::default();
+    assert_eq!(collector.container(&roots(), "podman", ID).name, None);
+    assert!(collector.containers.is_empty());
+  }
+}
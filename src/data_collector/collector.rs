@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config_manager::{CollectionConfig, CompiledTopProcesses, RESERVED_COLLECTOR_NAMES};
+use crate::filter::CompiledFilters;
+
+use super::block_devices::BlockDeviceCollector;
+use super::cgroups::CgroupCollector;
+use super::cpu::CpuCollector;
+use super::disks::{DiskCollector, NetworkDiskCollector};
+use super::gpu::GPUFetcher;
+use super::nics::NetworkCollector;
+use super::pressure::PressureCollector;
+use super::processes::{ProcessCountCollector, TopProcessesCollector};
+use super::ram::{RamCollector, SwapCollector};
+use super::systemd_units::SystemdCollector;
+use super::temps::TempCollector;
+
+/// What collectors get to work with on every run
+#[derive(Debug, Clone)]
+pub struct CollectContext {
+  pub filters: Arc<CompiledFilters>,
+  pub top_processes: Arc<CompiledTopProcesses>,
+  pub collection: Arc<CollectionConfig>,
+  /// Whether lists like the disks or network interfaces should be refreshed this run
+  pub refresh_lists: bool,
+  /// How long the run may take, collectors that start processes kill them after it
+  pub timeout: Duration,
+}
+
+/// How much earlier than its timeout a collector stops waiting on what it started at the latest
+const TIMEOUT_MARGIN: Duration = Duration::from_millis(250);
+
+/// How long a collector may wait on something it started, like a command or a statvfs call,
+/// so it can still report the hold-up itself before the run's own deadline passes first
+pub fn inner_timeout(timeout: Duration) -> Duration {
+  timeout.saturating_sub(TIMEOUT_MARGIN.min(timeout / 4))
+}
+
+/// A source of metrics for the dynamic data. The built-in collectors fill in their own field
+/// of the sample, anything else ends up in its `extra` map under the collector's name.
+pub trait Collector: Send {
+  /// Identifies the collector in the sample, the config and the errors
+  fn name(&self) -> &str;
+
+  /// Whether it runs when the config doesn't turn it on or off
+  fn enabled(&self) -> bool {
+    true
+  }
+
+  /// Seconds between runs when the config doesn't set an interval, None runs on every tick
+  fn interval(&self) -> Option<f64> {
+    None
+  }
+
+  /// Seconds a run may take when the config doesn't set a timeout
+  fn timeout(&self) -> Option<f64> {
+    None
+  }
+
+  /// Collects the collector's part of the sample, runs on a thread of its own
+  fn collect(&mut self, context: &CollectContext) -> Result<Value>;
+
+  /// Information that doesn't change at runtime, sent along with the static data
+  fn static_info(&mut self) -> Result<Option<Value>> {
+    Ok(None)
+  }
+}
+
+/// A registered collector, locked while it runs
+pub type SharedCollector = Arc<Mutex<Box<dyn Collector>>>;
+
+#[derive(Clone)]
+pub struct RegisteredCollector {
+  pub name: String,
+  /// The collector's own defaults, read once so scheduling a run
+  /// never has to wait for a hung collector's lock
+  pub enabled: bool,
+  pub interval: Option<f64>,
+  pub timeout: Option<f64>,
+  pub collector: SharedCollector,
+}
+
+/// The collectors that make up the dynamic data, in the order they were registered
+#[derive(Default)]
+pub struct CollectorRegistry {
+  collectors: Vec<RegisteredCollector>,
+}
+
+impl std::fmt::Debug for CollectorRegistry {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_list()
+      .entries(self.collectors.iter().map(|registered| &registered.name))
+      .finish()
+  }
+}
+
+impl CollectorRegistry {
+  /// Creates a registry with all of the built-in collectors
+  pub fn with_builtins() -> Result<Self> {
+    let mut registry = Self::default();
+    registry.register(Box::new(CpuCollector::new()?))?;
+    registry.register(Box::new(RamCollector::default()))?;
+    registry.register(Box::new(SwapCollector::default()))?;
+    registry.register(Box::new(GPUFetcher::new()))?;
+    registry.register(Box::new(ProcessCountCollector::default()))?;
+    registry.register(Box::new(DiskCollector::default()))?;
+    registry.register(Box::new(NetworkDiskCollector::default()))?;
+    registry.register(Box::new(TempCollector::new()))?;
+    registry.register(Box::new(NetworkCollector::default()))?;
+    registry.register(Box::new(TopProcessesCollector::new()))?;
+    registry.register(Box::new(PressureCollector))?;
+    registry.register(Box::new(CgroupCollector::default()))?;
+    registry.register(Box::new(BlockDeviceCollector::default()))?;
+    registry.register(Box::new(SystemdCollector::default()))?;
+    Ok(registry)
+  }
+
+  /// Adds a collector, names have to be unique and can't be one of the config's other keys
+  pub fn register(&mut self, collector: Box<dyn Collector>) -> Result<()> {
+    let name = collector.name().to_string();
+    if RESERVED_COLLECTOR_NAMES.contains(&name.as_str()) {
+      return Err(anyhow!("\"{}\" can't be used as a collector name", name));
+    }
+    if self.get(&name).is_some() {
+      return Err(anyhow!(
+        "A collector named \"{}\" is already registered",
+        name
+      ));
+    }
+
+    self.collectors.push(RegisteredCollector {
+      name,
+      enabled: collector.enabled(),
+      interval: collector.interval(),
+      timeout: collector.timeout(),
+      collector: Arc::new(Mutex::new(collector)),
+    });
+    Ok(())
+  }
+
+  /// Removes the collector of that name, a run that's in progress still finishes
+  pub fn unregister(&mut self, name: &str) {
+    self.collectors.retain(|registered| registered.name != name);
+  }
+
+  pub fn get(&self, name: &str) -> Option<&RegisteredCollector> {
+    self
+      .collectors
+      .iter()
+      .find(|registered| registered.name == name)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &RegisteredCollector> {
+    self.collectors.iter()
+  }
+}
@@ -1,9 +1,26 @@
 #[cfg(target_family = "unix")]
-use sysinfo::{ProcessorExt, SystemExt};
+use sysinfo::{ProcessorExt, RefreshKind, System, SystemExt};
 
 use crate::types::CPUStats;
 
+#[cfg(target_family = "unix")]
+use crate::types::LoadAverage;
+
+#[cfg(target_os = "linux")]
+use crate::types::{CpuTimes, SchedulerStats};
+
+#[cfg(target_os = "linux")]
+use super::procfs::{self, CounterHistory, CpuTicks, ProcStat};
+
+#[cfg(target_os = "linux")]
+use super::environment;
+
+use crate::config_manager::RootsConfig;
+
 use anyhow::Result;
+use serde_json::Value;
+
+use super::collector::{CollectContext, Collector};
 
 #[cfg(target_family = "windows")]
 use windows::Win32::System::Performance::*;
@@ -23,29 +40,283 @@ use std::mem;
 #[cfg(target_family = "windows")]
 use std::process::exit;
 
-use super::DataCollector;
+#[cfg(target_family = "windows")]
+use std::thread::sleep;
+
+#[cfg(target_family = "windows")]
+use windows::core::PCSTR;
+
+/// Keeps what's needed to measure the CPU usage between runs
+#[cfg(target_family = "unix")]
+#[derive(Debug)]
+pub struct CpuCollector {
+  system: System,
+  /// The /proc/stat counters
+  #[cfg(target_os = "linux")]
+  stat: CounterHistory<ProcStat>,
+  /// The CPU time of the reporter's container in microseconds
+  #[cfg(target_os = "linux")]
+  container: CounterHistory<u64>,
+}
+
+/// Percentages of the ticks between two reads of /proc/stat
+#[cfg(target_os = "linux")]
+fn cpu_times(now: &CpuTicks, last: &CpuTicks) -> CpuTimes {
+  let total = now.total().saturating_sub(last.total()).max(1) as f32;
+  let share = |now: u64, last: u64| now.saturating_sub(last) as f32 / total * 100.0;
+  CpuTimes {
+    user: share(now.user, last.user),
+    nice: share(now.nice, last.nice),
+    system: share(now.system, last.system),
+    idle: share(now.idle, last.idle),
+    iowait: share(now.iowait, last.iowait),
+    irq: share(now.irq, last.irq),
+    softirq: share(now.softirq, last.softirq),
+    steal: share(now.steal, last.steal),
+    guest: share(now.guest, last.guest),
+  }
+}
 
 #[cfg(target_family = "unix")]
-impl DataCollector {
+impl CpuCollector {
+  pub fn new() -> Result<Self> {
+    Ok(Self {
+      system: System::new_with_specifics(RefreshKind::new().with_cpu()),
+      #[cfg(target_os = "linux")]
+      stat: CounterHistory::default(),
+      #[cfg(target_os = "linux")]
+      container: CounterHistory::default(),
+    })
+  }
+
+  /// Gets the CPU time breakdown and scheduler stats since the last run
+  #[cfg(target_os = "linux")]
+  fn get_proc_stat(
+    &mut self,
+    roots: &RootsConfig,
+  ) -> Result<(CpuTimes, Vec<CpuTimes>, SchedulerStats)> {
+    let stat = ProcStat::read(roots)?;
+    let delta = self.stat.start();
+    let last = delta.last(&stat);
+
+    let times = cpu_times(&stat.cpu, &last.cpu);
+    let core_times = stat
+      .cores
+      .iter()
+      .enumerate()
+      .map(|(index, core)| cpu_times(core, last.cores.get(index).unwrap_or(core)))
+      .collect();
+    let scheduler = SchedulerStats {
+      context_switches: delta.rate(stat.context_switches, last.context_switches),
+      interrupts: delta.rate(stat.interrupts, last.interrupts),
+      forks: delta.rate(stat.forks, last.forks),
+      running: stat.running,
+      blocked: stat.blocked,
+    };
+
+    self.stat.finish(stat);
+    Ok((times, core_times, scheduler))
+  }
+
+  /// Gets how much of the cores it may use the reporter's container used since
+  /// the last run, None outside of a container
+  #[cfg(target_os = "linux")]
+  fn get_container_usage(&mut self, roots: &RootsConfig) -> Option<u16> {
+    let (usage, cores) = environment::container_cpu(roots)?;
+    let cores = cores.unwrap_or(self.system.processors().len() as f32) as f64;
+    let delta = self.container.start();
+    // The usage is in microseconds of CPU time
+    let used_cores = delta.rate(usage, *delta.last(&usage)) / 1_000_000.0;
+    self.container.finish(usage);
+    let percent = if cores > 0.0 {
+      used_cores / cores * 100.0
+    } else {
+      0.0
+    };
+    Some(percent.min(100.0) as u16)
+  }
+
   /// Gets the current CPU stats
   /// wait what the fuck this is an array of cores? 🥴👍
-  pub fn get_cpu(&mut self) -> Result<CPUStats> {
+  pub fn get_cpu(&mut self, roots: &RootsConfig) -> Result<CPUStats> {
     let (mut usage, mut freq) = (vec![], vec![]);
 
-    for processor in self.fetcher.processors() {
+    for processor in self.system.processors() {
       usage.push(processor.cpu_usage().floor() as u16);
       freq.push(processor.frequency() as u16);
     }
 
-    self.fetcher.refresh_cpu();
-
-    Ok(CPUStats { usage, freq })
+    self.system.refresh_cpu();
+
+    let load = self.system.load_average();
+    let load_average = Some(LoadAverage {
+      one: load.one,
+      five: load.five,
+      fifteen: load.fifteen,
+    });
+    // sysinfo always reads the reporter's own /proc
+    #[cfg(target_os = "linux")]
+    let load_average = if roots.is_own_proc() {
+      load_average
+    } else {
+      match procfs::read_load_average(roots) {
+        Ok(load_average) => Some(load_average),
+        Err(e) => {
+          debug!("No load average: {}", e);
+          None
+        }
+      }
+    };
+
+    // The breakdown is extra, the usage is still worth sending without it
+    #[cfg(target_os = "linux")]
+    let (times, core_times, scheduler) = match self.get_proc_stat(roots) {
+      Ok((times, core_times, scheduler)) => (Some(times), Some(core_times), Some(scheduler)),
+      Err(e) => {
+        debug!("No CPU time breakdown: {}", e);
+        (None, None, None)
+      }
+    };
+    #[cfg(not(target_os = "linux"))]
+    let (times, core_times, scheduler) = (None, None, None);
+    #[cfg(not(target_os = "linux"))]
+    let _ = roots;
+
+    Ok(CPUStats {
+      usage,
+      freq,
+      load_average,
+      times,
+      core_times,
+      scheduler,
+      container_usage: None,
+    })
   }
 }
 
+/// Keeps the perfmon query and the last valid counter data between runs
 #[cfg(target_family = "windows")]
-impl DataCollector {
-  pub fn get_cpu(&mut self) -> Result<CPUStats> {
+#[derive(Debug)]
+pub struct CpuCollector {
+  pub pdh_query: isize,
+  pub pdh_proc_perf_counter: isize,
+  pub pdh_proc_freq_counter: isize,
+  pub pdh_proc_util_counter: isize,
+  pub pdh_proc_perf_data_cached: *mut PDH_FMT_COUNTERVALUE_ITEM_A,
+  pub pdh_proc_perf_data_len: usize,
+  pub pdh_proc_perf_data_capacity: usize,
+  pub pdh_proc_freq_data_cached: *mut PDH_FMT_COUNTERVALUE_ITEM_A,
+  pub pdh_proc_freq_data_len: usize,
+  pub pdh_proc_freq_data_capacity: usize,
+  pub pdh_proc_util_data_cached: *mut PDH_FMT_COUNTERVALUE_ITEM_A,
+  pub pdh_proc_util_data_len: usize,
+  pub pdh_proc_util_data_capacity: usize,
+  pub first_pdh_called: bool,
+}
+
+// The cached counter data is only ever touched by whichever thread holds the collector's lock
+#[cfg(target_family = "windows")]
+unsafe impl Send for CpuCollector {}
+
+#[cfg(target_family = "windows")]
+impl CpuCollector {
+  /// Sets up the perfmon queries for the CPU counters
+  pub fn new() -> Result<Self> {
+    let mut pdh_query = 0 as isize;
+    let mut pdh_proc_perf_counter = 0 as isize;
+    let mut pdh_proc_freq_counter = 0 as isize;
+    let mut pdh_proc_util_counter = 0 as isize;
+
+    let pdh_proc_perf_data_cached = ptr::null_mut();
+    let pdh_proc_freq_data_cached = ptr::null_mut();
+    let pdh_proc_util_data_cached = ptr::null_mut();
+
+    unsafe {
+      // Establishes our means to query the winapi performance monitoring system.
+      let ret = PdhOpenQueryA(PCSTR::default(), 0, &mut pdh_query as *mut isize);
+
+      if ret != ERROR_SUCCESS.0 as i32 {
+        error!("Unable to open perfmon query. Errno: {}", ret);
+        exit(ret);
+      }
+
+      // Metric to determine per logical CPU speed relative to base clock of CPU.
+      // Example: CPU base frequency is 3500 Mhz and processor performance is 106.
+      //          Performance value should be interpreted as a percentage, so in this case
+      //          base_freq * (performance / 100) = 3710 Mhz.
+      let ret = PdhAddEnglishCounterA(pdh_query,
+                                      "\\Processor Information(*)\\% Processor Performance",
+                                      0,
+                                      &mut pdh_proc_perf_counter as *mut isize);
+
+      if ret != ERROR_SUCCESS.0 as i32 {
+        error!("Unable to add processor performance counter to perfmon query. Errno: {}", ret);
+        exit(ret);
+      }
+
+      // Do I really have to say it?
+      let ret = PdhAddEnglishCounterA(pdh_query,
+                                      "\\Processor Information(*)\\Processor Frequency",
+                                      0,
+                                      &mut pdh_proc_freq_counter as *mut isize);
+
+      if ret != ERROR_SUCCESS.0 as i32 {
+        error!("Unable to add processor frequency counter to perfmon query. Errno: {}", ret);
+        exit(ret);
+      }
+
+      // Processor utilization per core.
+      let ret = PdhAddEnglishCounterA(pdh_query,
+                                      "\\Processor Information(*)\\% Processor Utility",
+                                      0,
+                                      &mut pdh_proc_util_counter as *mut isize);
+
+      if ret != ERROR_SUCCESS.0 as i32 {
+        error!("Unable to add processor performance utilization to perfmon query. Errno: {}", ret);
+        exit(ret);
+      }
+
+      // We have to prime the query data with an initial point of reference. If you
+      // don't do this the data retrieved the first time around will either be zeroed or
+      // garbage data that won't be useful to you.
+      let ret = PdhCollectQueryData(pdh_query);
+
+      if ret != ERROR_SUCCESS.0 as i32 {
+        error!("Unable to initialize perfmon queries. Errno: {}", ret);
+        exit(ret);
+      }
+
+      // Guarantees the next invocation of PdhCollectQueryData will yield valid data.
+      sleep(std::time::Duration::from_millis(1000));
+    }
+
+    Ok(Self {
+      pdh_query,
+      pdh_proc_perf_counter,
+      pdh_proc_freq_counter,
+      pdh_proc_util_counter,
+      pdh_proc_perf_data_cached,
+      pdh_proc_perf_data_len: 0,
+      pdh_proc_perf_data_capacity: 0,
+      pdh_proc_freq_data_cached,
+      pdh_proc_freq_data_len: 0,
+      pdh_proc_freq_data_capacity: 0,
+      pdh_proc_util_data_cached,
+      pdh_proc_util_data_len: 0,
+      pdh_proc_util_data_capacity: 0,
+      first_pdh_called: false,
+    })
+  }
+
+  pub fn get_cpu(&mut self, _roots: &RootsConfig) -> Result<CPUStats> {
+    unsafe {
+      let ret = PdhCollectQueryData(self.pdh_query);
+      if ret != ERROR_SUCCESS.0 as i32 {
+        error!("Unable to query latest CPU information from perfmon.");
+        exit(1);
+      }
+    }
+
     let (mut usage, mut freq) = (vec![], vec![]);
 
     // Stores information about the capacity of the buffer needed for retrieving
@@ -88,7 +359,7 @@ impl DataCollector {
       // an actual buffer, it can't write anything. Any status other than this is
       // indicative of a problem.
       if ret != PDH_MORE_DATA {
-        eprintln!("Unable to fetch processor frequency data. Errno: {}", ret);
+        error!("Unable to fetch processor frequency data. Errno: {}", ret);
         exit(ret);
       }
 
@@ -99,7 +370,7 @@ impl DataCollector {
                                              ptr::null_mut());
 
       if ret != PDH_MORE_DATA {
-        eprintln!("Unable to fetch processor performance data. Errno: {}", ret);
+        error!("Unable to fetch processor performance data. Errno: {}", ret);
         exit(ret);
       }
 
@@ -110,7 +381,7 @@ impl DataCollector {
                                              ptr::null_mut());
 
       if ret != PDH_MORE_DATA {
-        eprintln!("Unable to fetch processor utilization data. Errno: {}", ret);
+        error!("Unable to fetch processor utilization data. Errno: {}", ret);
         exit(ret);
       }
 
@@ -140,7 +411,7 @@ impl DataCollector {
                                    proc_freq_data.as_mut_ptr());
 
       if ret != ERROR_SUCCESS.0 as i32 {
-          eprintln!("Unable to fetch processor frequency data. Errno: {}", ret);
+          error!("Unable to fetch processor frequency data. Errno: {}", ret);
           exit(ret);
       }
 
@@ -152,7 +423,7 @@ impl DataCollector {
                                    proc_perf_data.as_mut_ptr());
 
       if ret != ERROR_SUCCESS.0 as i32 {
-          eprintln!("Unable to fetch processor performance data. Errno: {}", ret);
+          error!("Unable to fetch processor performance data. Errno: {}", ret);
           exit(ret);
       }
 
@@ -164,7 +435,7 @@ impl DataCollector {
                                                   proc_util_data.as_mut_ptr());
 
       if ret != ERROR_SUCCESS.0 as i32 {
-          eprintln!("Unable to fetch processor utilization data. Errno: {}", ret);
+          error!("Unable to fetch processor utilization data. Errno: {}", ret);
           exit(ret);
       }
 
@@ -178,7 +449,7 @@ impl DataCollector {
         // try again on the next poll. In which case the issue will resolve itself.
 
         if self.pdh_proc_freq_data_len == 0 {
-            eprintln!("Unable to fetch data from counters and nothing is cached.");
+            error!("Unable to fetch data from counters and nothing is cached.");
             exit(1);
         }
         ptr_freq_data_target = self.pdh_proc_freq_data_cached;
@@ -241,7 +512,7 @@ impl DataCollector {
                 i = i + 1;
                 continue;
             } else if str_slice.contains("oh shit") {
-                eprintln!("Inconsistent data received from perfmon. Aborting.");
+                error!("Inconsistent data received from perfmon. Aborting.");
                 exit(1);
             }
 
@@ -255,6 +526,36 @@ impl DataCollector {
         }
     }
 
-    Ok(CPUStats { usage, freq })
+    Ok(CPUStats {
+      usage,
+      freq,
+      load_average: None,
+      times: None,
+      core_times: None,
+      scheduler: None,
+      container_usage: None,
+    })
   }
-}
\ No newline at end of file
+}
+
+#[cfg(not(target_os = "linux"))]
+impl CpuCollector {
+  fn get_container_usage(&mut self, _roots: &RootsConfig) -> Option<u16> {
+    None
+  }
+}
+
+impl Collector for CpuCollector {
+  fn name(&self) -> &str {
+    "cpu"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let roots = &context.collection.roots;
+    let mut cpu = self.get_cpu(roots)?;
+    if context.collection.container.scope_to_cgroup {
+      cpu.container_usage = self.get_container_usage(roots);
+    }
+    Ok(serde_json::to_value(cpu)?)
+  }
+}
@@ -1,31 +1,327 @@
 use anyhow::Result;
-use sysinfo::{DiskExt, SystemExt};
+use serde_json::Value;
+use std::time::Duration;
 
+use crate::config_manager::RootsConfig;
+use crate::filter::CompiledFilters;
 use crate::types::DiskStats;
 
-use super::DataCollector;
+use super::collector::{inner_timeout, CollectContext, Collector};
+
+#[cfg(target_os = "linux")]
+use super::procfs::{self, Mount};
+
+#[cfg(target_os = "linux")]
+use std::{
+  collections::HashMap,
+  ffi::CString,
+  io, mem,
+  os::unix::ffi::OsStrExt,
+  path::{Path, PathBuf},
+  sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+  thread,
+  time::Instant,
+};
+
+#[cfg(not(target_os = "linux"))]
+use sysinfo::{DiskExt, System, SystemExt};
+
+#[cfg(not(target_os = "linux"))]
+use super::DataCollectorError;
+
+/// File systems that aren't on a disk, the same ones sysinfo leaves out
+#[cfg(target_os = "linux")]
+const IGNORED_FILESYSTEMS: [&str; 11] = [
+  "rootfs",
+  "sysfs",
+  "proc",
+  "tmpfs",
+  "devtmpfs",
+  "cgroup",
+  "cgroup2",
+  "pstore",
+  "squashfs",
+  "rpc_pipefs",
+  "iso9660",
+];
+
+/// File systems served over the network, which hang for as long as their server is gone.
+/// FUSE file systems count as well unless they're in LOCAL_FUSE_FILESYSTEMS.
+#[cfg(target_os = "linux")]
+const NETWORK_FILESYSTEMS: [&str; 15] = [
+  "nfs",
+  "nfs4",
+  "cifs",
+  "smb3",
+  "smbfs",
+  "ncpfs",
+  "afs",
+  "ceph",
+  "glusterfs",
+  "lustre",
+  "9p",
+  "davfs",
+  "beegfs",
+  "gpfs",
+  "orangefs",
+];
+
+/// FUSE file systems that are backed by local files or disks
+#[cfg(target_os = "linux")]
+const LOCAL_FUSE_FILESYSTEMS: [&str; 10] = [
+  "fuseblk",
+  "fuse.lxcfs",
+  "fuse.mergerfs",
+  "fuse.bindfs",
+  "fuse.encfs",
+  "fuse.gocryptfs",
+  "fuse.squashfuse",
+  "fuse.ntfs-3g",
+  "fuse.portal",
+  "fuse.AppImage",
+];
+
+/// The space and inodes of a mounted file system from statvfs
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+  total: u64,
+  available: u64,
+  inodes_total: u64,
+  inodes_free: u64,
+}
+
+/// Gets the usage of the file system mounted at the path, which blocks
+/// until a network file system's server answers
+#[cfg(target_os = "linux")]
+// The statvfs fields are 32 bits wide on some targets
+#[allow(clippy::unnecessary_cast)]
+fn statvfs(path: &Path) -> io::Result<Usage> {
+  let path = CString::new(path.as_os_str().as_bytes())
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+  // SAFETY: statvfs only writes to the struct, which is plain data, and the path is
+  // a valid C string that outlives the call
+  let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+  if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+    return Err(io::Error::last_os_error());
+  }
+
+  let fragment = stat.f_frsize as u64;
+  Ok(Usage {
+    total: stat.f_blocks as u64 * fragment,
+    available: stat.f_bavail as u64 * fragment,
+    inodes_total: stat.f_files as u64,
+    inodes_free: stat.f_ffree as u64,
+  })
+}
+
+/// Whether the mount is something other than a disk, like /proc or a tmpfs
+#[cfg(target_os = "linux")]
+fn is_ignored(mount: &Mount) -> bool {
+  let mount_point = mount.mount_point.as_str();
+  IGNORED_FILESYSTEMS.contains(&mount.fs_type.as_str())
+    || mount_point.starts_with("/sys")
+    || mount_point.starts_with("/proc")
+    || (mount_point.starts_with("/run") && !mount_point.starts_with("/run/media"))
+    || mount.source.starts_with("sunrpc")
+}
+
+/// Whether the mount is served over the network, any FUSE file system that isn't known to be
+/// local counts since it can hang just the same, like rclone, s3fs or sshfs
+#[cfg(target_os = "linux")]
+fn is_network(mount: &Mount) -> bool {
+  let fs_type = mount.fs_type.as_str();
+  if LOCAL_FUSE_FILESYSTEMS.contains(&fs_type) {
+    return false;
+  }
+  NETWORK_FILESYSTEMS.contains(&fs_type) || fs_type == "fuse" || fs_type.starts_with("fuse.")
+}
+
+#[cfg(target_os = "linux")]
+fn allows(filters: &CompiledFilters, mount: &Mount) -> bool {
+  filters.disks.allows(&mount.source)
+    && filters.mounts.allows(&mount.mount_point)
+    && filters.filesystems.allows(&mount.fs_type)
+}
+
+/// The stats of a mount, a file system that didn't answer has no usage
+#[cfg(target_os = "linux")]
+fn disk_stats(roots: &RootsConfig, mount: Mount, usage: Option<Usage>) -> DiskStats {
+  let device = procfs::block_device_name(roots, mount.device);
+  let r#type = match &device {
+    Some(device) => procfs::block_device_type(roots, device),
+    None => "Unknown(-1)".to_string(),
+  };
+  // File systems like btrfs and vfat have no fixed number of inodes and report 0
+  let inodes = usage.filter(|usage| usage.inodes_total > 0);
+
+  DiskStats {
+    name: mount.source,
+    mount: mount.mount_point,
+    fs: mount.fs_type,
+    r#type,
+    total: usage.map_or(0, |usage| usage.total),
+    used: usage.map_or(0, |usage| usage.total.saturating_sub(usage.available)),
+    inodes_total: inodes.map(|usage| usage.inodes_total),
+    inodes_used: inodes.map(|usage| usage.inodes_total.saturating_sub(usage.inodes_free)),
+    inodes_free: inodes.map(|usage| usage.inodes_free),
+    read_only: Some(mount.options.iter().any(|option| option == "ro")),
+    options: Some(mount.options),
+    device,
+    timed_out: usage.is_none(),
+  }
+}
+
+/// Runs statvfs on a thread of its own so the caller can give up on it
+#[cfg(target_os = "linux")]
+fn spawn_statvfs(path: PathBuf) -> Receiver<io::Result<Usage>> {
+  let (sender, receiver) = mpsc::channel();
+  // The sender is dropped if the thread can't be spawned, which the receiver sees as an error
+  let spawned = thread::Builder::new().spawn(move || {
+    let _ = sender.send(statvfs(&path));
+  });
+  if let Err(e) = spawned {
+    debug!("Could not spawn a thread for statvfs: {}", e);
+  }
+  receiver
+}
+
+/// statvfs calls that run on threads of their own so a file system that hangs can't hold up
+/// the run. A call that didn't answer in time is kept by mount point, and the file system
+/// isn't asked again until it returns.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default)]
+struct TimedStatvfs {
+  pending: HashMap<String, Receiver<io::Result<Usage>>>,
+}
+
+#[cfg(target_os = "linux")]
+impl TimedStatvfs {
+  /// Gets the usage of the mounts, None for the ones that didn't answer within the timeout.
+  /// Mounts whose call failed are left out.
+  fn stat(
+    &mut self,
+    mounts: Vec<Mount>,
+    roots: &RootsConfig,
+    timeout: Duration,
+  ) -> Vec<(Mount, Option<Usage>)> {
+    // The threads of file systems that were unmounted end whenever their call does
+    self
+      .pending
+      .retain(|mount_point, _| mounts.iter().any(|mount| &mount.mount_point == mount_point));
+
+    let mut calls = Vec::new();
+    for mount in mounts {
+      let call = match self.pending.remove(&mount.mount_point) {
+        Some(receiver) if matches!(receiver.try_recv(), Err(TryRecvError::Empty)) => {
+          self.pending.insert(mount.mount_point.clone(), receiver);
+          None
+        }
+        _ => Some(spawn_statvfs(roots.root(&mount.mount_point))),
+      };
+      calls.push((mount, call));
+    }
+
+    // Every call runs at the same time, so they share the one deadline
+    let deadline = Instant::now() + timeout;
+    let mut usages = Vec::new();
+    for (mount, call) in calls {
+      let receiver = match call {
+        Some(receiver) => receiver,
+        None => {
+          usages.push((mount, None));
+          continue;
+        }
+      };
+
+      match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(Ok(usage)) => usages.push((mount, Some(usage))),
+        Ok(Err(e)) => debug!("Could not get the usage of {}: {}", mount.mount_point, e),
+        Err(RecvTimeoutError::Timeout) => {
+          debug!("{} didn't answer in {:?}", mount.mount_point, timeout);
+          self.pending.insert(mount.mount_point.clone(), receiver);
+          usages.push((mount, None));
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+          debug!("Could not get the usage of {}", mount.mount_point)
+        }
+      }
+    }
+    usages
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct DiskCollector {
+  #[cfg(target_os = "linux")]
+  statvfs: TimedStatvfs,
+  #[cfg(not(target_os = "linux"))]
+  system: System,
+}
+
+impl DiskCollector {
+  /// Gets the current disk(s) stats, the mounts are read again on every run. A disk that
+  /// doesn't answer within the timeout, like a failing one, is sent without its usage.
+  #[cfg(target_os = "linux")]
+  pub fn get_disks(
+    &mut self,
+    filters: &CompiledFilters,
+    _refresh_list: bool,
+    roots: &RootsConfig,
+    timeout: Duration,
+  ) -> Result<Vec<DiskStats>> {
+    // Network file systems have a collector of their own
+    let mounts = procfs::read_mounts(roots)?
+      .into_iter()
+      .filter(|mount| !is_ignored(mount) && !is_network(mount) && allows(filters, mount))
+      .collect();
+
+    let mut disks = Vec::<DiskStats>::new();
+    for (mount, usage) in self.statvfs.stat(mounts, roots, timeout) {
+      match usage {
+        Some(usage) if usage.total == 0 => {}
+        usage => disks.push(disk_stats(roots, mount, usage)),
+      }
+    }
+    Ok(disks)
+  }
 
-impl DataCollector {
   /// Gets the current disk(s) stats
-  pub fn get_disks(&mut self) -> Result<Vec<DiskStats>> {
+  #[cfg(not(target_os = "linux"))]
+  pub fn get_disks(
+    &mut self,
+    filters: &CompiledFilters,
+    refresh_list: bool,
+    _roots: &RootsConfig,
+    _timeout: Duration,
+  ) -> Result<Vec<DiskStats>> {
     let mut disks = Vec::<DiskStats>::new();
-    self.fetcher.refresh_disks_list();
-    for disk in self.fetcher.disks() {
+    if refresh_list {
+      debug!("Refreshing the disk list");
+      self.system.refresh_disks_list();
+    } else {
+      self.system.refresh_disks();
+    }
+
+    for disk in self.system.disks() {
       let (name, mount) = (
         disk.name().to_string_lossy(),
         disk.mount_point().to_string_lossy(),
       );
 
-      if name.contains("docker") || mount.contains("docker") || mount.contains("boot") {
-        continue;
-      }
-
       let (fs_type, mut str) = (disk.file_system(), String::from(""));
 
       for unit in fs_type {
         str.push(*unit as char);
       }
 
+      if !filters.disks.allows(&name)
+        || !filters.mounts.allows(&mount)
+        || !filters.filesystems.allows(&str)
+      {
+        continue;
+      }
+
       let disk = DiskStats {
         name: format!("{}", disk.name().to_string_lossy()),
         mount: format!("{}", disk.mount_point().to_string_lossy()),
@@ -33,6 +329,13 @@ impl DataCollector {
         r#type: format!("{:?}", disk.type_()),
         total: disk.total_space(),
         used: disk.total_space() - disk.available_space(),
+        inodes_total: None,
+        inodes_used: None,
+        inodes_free: None,
+        read_only: None,
+        options: None,
+        device: None,
+        timed_out: false,
       };
 
       disks.push(disk);
@@ -40,3 +343,129 @@ impl DataCollector {
     Ok(disks)
   }
 }
+
+impl Collector for DiskCollector {
+  fn name(&self) -> &str {
+    "disks"
+  }
+
+  fn interval(&self) -> Option<f64> {
+    Some(30.0)
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let roots = &context.collection.roots;
+    let timeout = inner_timeout(context.timeout);
+    let disks = self.get_disks(&context.filters, context.refresh_lists, roots, timeout)?;
+    Ok(serde_json::to_value(disks)?)
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct NetworkDiskCollector {
+  #[cfg(target_os = "linux")]
+  statvfs: TimedStatvfs,
+}
+
+impl NetworkDiskCollector {
+  /// Gets the stats of the network file systems, the ones that take longer than
+  /// the timeout are sent without their usage
+  #[cfg(target_os = "linux")]
+  pub fn get_network_disks(
+    &mut self,
+    filters: &CompiledFilters,
+    roots: &RootsConfig,
+    stat_timeout: Duration,
+  ) -> Result<Vec<DiskStats>> {
+    let mounts: Vec<Mount> = procfs::read_mounts(roots)?
+      .into_iter()
+      .filter(|mount| is_network(mount) && allows(filters, mount))
+      .collect();
+
+    let disks = self
+      .statvfs
+      .stat(mounts, roots, stat_timeout)
+      .into_iter()
+      .map(|(mount, usage)| disk_stats(roots, mount, usage))
+      .collect();
+    Ok(disks)
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  pub fn get_network_disks(
+    &mut self,
+    _filters: &CompiledFilters,
+    _roots: &RootsConfig,
+    _stat_timeout: Duration,
+  ) -> Result<Vec<DiskStats>> {
+    Err(DataCollectorError::Unsupported.into())
+  }
+}
+
+impl Collector for NetworkDiskCollector {
+  fn name(&self) -> &str {
+    "network_disks"
+  }
+
+  fn interval(&self) -> Option<f64> {
+    Some(30.0)
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    // Capped so a stat_timeout longer than the collector's can't turn into a timeout of it all
+    let stat_timeout = Duration::from_secs_f64(context.collection.network_disks.stat_timeout)
+      .min(inner_timeout(context.timeout));
+    let roots = &context.collection.roots;
+    let disks = self.get_network_disks(&context.filters, roots, stat_timeout)?;
+    Ok(serde_json::to_value(disks)?)
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  fn mount(fs_type: &str, mount_point: &str) -> Mount {
+    Mount {
+      device: (0, 42),
+      mount_point: mount_point.to_string(),
+      options: vec!["rw".to_string()],
+      fs_type: fs_type.to_string(),
+      source: "source".to_string(),
+    }
+  }
+
+  #[test]
+  fn fuse_file_systems_count_as_network_unless_known_to_be_local() {
+    for fs_type in &[
+      "nfs4",
+      "cifs",
+      "davfs",
+      "fuse",
+      "fuse.rclone",
+      "fuse.s3fs",
+      "fuse.sshfs",
+    ] {
+      assert!(is_network(&mount(fs_type, "/mnt")), "{}", fs_type);
+    }
+    for fs_type in &["ext4", "btrfs", "fuseblk", "fuse.lxcfs", "fuse.mergerfs"] {
+      assert!(!is_network(&mount(fs_type, "/mnt")), "{}", fs_type);
+    }
+  }
+
+  #[test]
+  fn gets_the_usage_of_mounts_that_answer() {
+    let root = env!("CARGO_MANIFEST_DIR").to_string();
+    let mut statvfs = TimedStatvfs::default();
+    let usages = statvfs.stat(
+      vec![mount("ext4", &root), mount("ext4", "/does/not/exist")],
+      &RootsConfig::default(),
+      Duration::from_secs(5),
+    );
+    // The mount that doesn't exist fails and is left out
+    assert_eq!(usages.len(), 1);
+    assert_eq!(usages[0].0.mount_point, root);
+    assert!(usages[0].1.is_some_and(|usage| usage.total > 0));
+    assert!(statvfs.pending.is_empty());
+  }
+}
@@ -0,0 +1,216 @@
+use crate::config_manager::RootsConfig;
+
+#[cfg(target_os = "linux")]
+use super::procfs;
+
+#[cfg(target_os = "linux")]
+use std::fs;
+
+/// The container runtime the reporter runs in, if any, or with the roots of a host the one it runs in
+#[cfg(target_os = "linux")]
+pub fn detect_container(roots: &RootsConfig) -> Option<String> {
+  // The reporter's environment says nothing about a host whose procfs it reads
+  if roots.is_own_proc() && std::env::var_os("KUBERNETES_SERVICE_HOST").is_some() {
+    return Some("kubernetes".to_string());
+  }
+  if roots.root("/.dockerenv").exists() {
+    return Some("docker".to_string());
+  }
+  if roots.root("/run/.containerenv").exists() {
+    return Some("podman".to_string());
+  }
+
+  // systemd-nspawn and LXC tell the init process what it runs in
+  let environ = fs::read(roots.proc("1/environ")).unwrap_or_default();
+  let container = environ
+    .split(|byte| *byte == 0)
+    .find_map(|variable| variable.strip_prefix(b"container="));
+  if let Some(container) = container {
+    return Some(String::from_utf8_lossy(container).to_string());
+  }
+
+  // Without a cgroup namespace the runtime's name is in the path of the reporter's group
+  let cgroup = procfs::read(roots, &roots.process("cgroup")).ok()?;
+  cgroup
+    .lines()
+    .filter_map(|line| line.splitn(3, ':').nth(2))
+    .find_map(runtime_of_cgroup)
+    .map(str::to_string)
+}
+
+/// The runtime whose container a cgroup path is in, from the names the runtimes give their
+/// groups like `docker-<id>.scope`, `/docker/<id>`, `kubepods-*.slice` or `lxc.payload.<name>`,
+/// and not from groups that merely mention one like `containerd.service`
+#[cfg(target_os = "linux")]
+fn runtime_of_cgroup(path: &str) -> Option<&'static str> {
+  let segments: Vec<&str> = path
+    .split('/')
+    .filter(|segment| !segment.is_empty())
+    .collect();
+  // Pods are checked first since their containers are in a group of containerd or CRI-O
+  if segments
+    .iter()
+    .any(|segment| segment.starts_with("kubepods"))
+  {
+    return Some("kubernetes");
+  }
+  let lxc = segments.iter().enumerate().any(|(index, segment)| {
+    segment.starts_with("lxc.payload.") || (index > 0 && segments[index - 1] == "lxc")
+  });
+  if lxc {
+    return Some("lxc");
+  }
+  (1..=segments.len())
+    .find_map(|end| procfs::container_id(&segments[..end].join("/")).map(|(runtime, _)| runtime))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect_container(_roots: &RootsConfig) -> Option<String> {
+  None
+}
+
+/// The hypervisor the machine runs on, if any, `unknown` for
+/// one that sets the CPU's hypervisor flag but doesn't say which it is
+#[cfg(target_os = "linux")]
+pub fn detect_virtualization(roots: &RootsConfig) -> Option<String> {
+  let dmi = |file: &str| {
+    fs::read_to_string(roots.sys("class/dmi/id").join(file))
+      .map(|value| value.trim().to_string())
+      .unwrap_or_default()
+  };
+  let (product, vendor, bios) = (dmi("product_name"), dmi("sys_vendor"), dmi("bios_vendor"));
+
+  if vendor == "Microsoft Corporation" && product == "Virtual Machine" {
+    return Some("hyperv".to_string());
+  }
+  let known = [
+    ("KVM", "kvm"),
+    ("QEMU", "qemu"),
+    ("VMware", "vmware"),
+    ("VirtualBox", "virtualbox"),
+    ("innotek", "virtualbox"),
+    ("Xen", "xen"),
+    ("Amazon EC2", "amazon"),
+    ("Google", "google"),
+    ("Parallels", "parallels"),
+    ("BHYVE", "bhyve"),
+    ("Bochs", "bochs"),
+  ];
+  for text in [&product, &vendor, &bios].iter() {
+    if let Some((_, name)) = known.iter().find(|(pattern, _)| text.contains(pattern)) {
+      return Some(name.to_string());
+    }
+  }
+
+  if let Ok(hypervisor) = fs::read_to_string(roots.sys("hypervisor/type")) {
+    return Some(hypervisor.trim().to_string());
+  }
+
+  // Hypervisors without DMI like Firecracker still set the flag
+  let cpuinfo = procfs::read(roots, "cpuinfo").ok()?;
+  let flagged = cpuinfo
+    .lines()
+    .filter(|line| line.starts_with("flags"))
+    .any(|line| line.split_whitespace().any(|flag| flag == "hypervisor"));
+  if flagged {
+    Some("unknown".to_string())
+  } else {
+    None
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect_virtualization(_roots: &RootsConfig) -> Option<String> {
+  None
+}
+
+/// The memory the reporter's container uses in bytes, without the page cache it can
+/// drop like `docker stats`, and its limit. None outside of a container.
+#[cfg(target_os = "linux")]
+pub fn container_memory(roots: &RootsConfig) -> Option<(u64, Option<u64>)> {
+  detect_container(roots)?;
+  let group = procfs::own_cgroup(roots)?;
+  let current = procfs::read_cgroup_value(&group.join("memory.current"))?;
+  let inactive = procfs::read_cgroup_values(&group.join("memory.stat"))
+    .and_then(|stat| stat.get("inactive_file").copied())
+    .unwrap_or(0);
+  let max = procfs::read_cgroup_value(&group.join("memory.max"));
+  Some((current.saturating_sub(inactive), max))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn container_memory(_roots: &RootsConfig) -> Option<(u64, Option<u64>)> {
+  None
+}
+
+/// The CPU time the reporter's container used in microseconds and how many
+/// cores it may use, from its CPU limit or its cpuset. None outside of a container.
+#[cfg(target_os = "linux")]
+pub fn container_cpu(roots: &RootsConfig) -> Option<(u64, Option<f32>)> {
+  detect_container(roots)?;
+  let group = procfs::own_cgroup(roots)?;
+  let usage = *procfs::read_cgroup_values(&group.join("cpu.stat"))?.get("usage_usec")?;
+  let cores = procfs::read_cgroup_cpu_limit(&group)
+    .or_else(|| procfs::read_cgroup_cpus(&group).map(|cpus| cpus as f32));
+  Some((usage, cores))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+  use std::path::Path;
+
+  #[test]
+  fn finds_no_container_around_a_host() {
+    let host = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/host");
+    let roots = RootsConfig {
+      proc: host.join("proc"),
+      sys: host.join("sys"),
+      root: host.clone(),
+    };
+    assert_eq!(detect_container(&roots), None);
+  }
+
+  const ID: &str = "3f4a9c1e5b7d2a8f6e0c4b9a1d3e5f7a9b2c4d6e8f0a1b3c5d7e9f1a2b3c4d5e";
+
+  #[test]
+  fn detects_runtimes_by_the_names_of_their_groups() {
+    let cases = [
+      (format!("/system.slice/docker-{}.scope", ID), Some("docker")),
+      (format!("/docker/{}", ID), Some("docker")),
+      (
+        format!("/machine.slice/libpod-{}.scope/container", ID),
+        Some("podman"),
+      ),
+      (
+        format!("/system.slice/cri-containerd-{}.scope", ID),
+        Some("containerd"),
+      ),
+      (
+        format!("/kubepods.slice/kubepods-burstable.slice/crio-{}.scope", ID),
+        Some("kubernetes"),
+      ),
+      ("/lxc.payload.web/init.scope".to_string(), Some("lxc")),
+      ("/lxc/web".to_string(), Some("lxc")),
+    ];
+    for (path, runtime) in cases.iter() {
+      assert_eq!(runtime_of_cgroup(path), *runtime, "{}", path);
+    }
+  }
+
+  #[test]
+  fn ignores_groups_that_only_mention_a_runtime() {
+    let paths = [
+      "/",
+      "/system.slice/containerd.service",
+      "/system.slice/docker.service",
+      "/system.slice/system-docker\\x2dcompose.slice/docker-compose@web.service",
+      "/user.slice/user-1000.slice/session-2.scope",
+      "/lxc.monitor.web",
+      "/system.slice/docker-short.scope",
+    ];
+    for path in paths.iter() {
+      assert_eq!(runtime_of_cgroup(path), None, "{}", path);
+    }
+  }
+}
@@ -0,0 +1,292 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::io::{self, Read};
+#[cfg(target_os = "linux")]
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config_manager::{ExecCollectorConfig, ExecFormat};
+
+use super::collector::{inner_timeout, CollectContext, Collector};
+use super::DataCollectorError;
+
+/// How often a running command is checked on
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How much of stderr is kept for the error
+const MAX_STDERR_LENGTH: usize = 1024;
+/// How much a command may print, more than that is an error rather than a metric
+const MAX_OUTPUT_LENGTH: usize = 4 * 1024 * 1024;
+
+/// Runs a configured executable and reports what it prints as custom metrics
+#[derive(Debug)]
+pub struct ExecCollector {
+  config: ExecCollectorConfig,
+}
+
+impl ExecCollector {
+  pub fn new(config: ExecCollectorConfig) -> Self {
+    Self { config }
+  }
+}
+
+/// Runs a command within a collector's timeout. It's killed along with anything it started
+/// a little before the timeout has passed, and stdout past MAX_OUTPUT_LENGTH is an error.
+pub(super) fn run(
+  command: &str,
+  args: &[String],
+  timeout: Duration,
+) -> Result<(ExitStatus, String, String)> {
+  let mut command_builder = Command::new(command);
+  command_builder
+    .args(args)
+    // Anything that speaks sd_notify would otherwise talk to systemd as the reporter
+    .env_remove("NOTIFY_SOCKET")
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  // A group of its own lets a timeout kill whatever the command started as well
+  #[cfg(target_os = "linux")]
+  command_builder.process_group(0);
+  let mut child = command_builder
+    .spawn()
+    .map_err(|e| anyhow::anyhow!("Could not run {}: {}", command, e))?;
+
+  // Read both pipes while waiting so a chatty command can't fill them up and block
+  let stdout = read_in_background(child.stdout.take());
+  let stderr = read_in_background(child.stderr.take());
+
+  let deadline = Instant::now() + inner_timeout(timeout);
+  let status = match wait_with_timeout(&mut child, deadline)? {
+    Some(status) => status,
+    None => {
+      // The readers aren't waited for since anything the command started
+      // can keep its pipes open after it's gone
+      kill(&mut child)?;
+      child.wait()?;
+      return Err(DataCollectorError::Timeout(timeout.as_secs_f64()).into());
+    }
+  };
+  // Whatever the command left running in the background would keep its pipes open, and
+  // something that left the group is only waited for until the deadline
+  kill_group(&child);
+
+  let left = deadline.saturating_duration_since(Instant::now());
+  let (stdout, truncated) = stdout
+    .recv_timeout(left)
+    .map_err(|_| DataCollectorError::Timeout(timeout.as_secs_f64()))?;
+  if truncated {
+    return Err(
+      DataCollectorError::InvalidOutput(format!("printed more than {} bytes", MAX_OUTPUT_LENGTH))
+        .into(),
+    );
+  }
+  let left = deadline.saturating_duration_since(Instant::now());
+  let (stderr, _) = stderr.recv_timeout(left).unwrap_or_default();
+  Ok((status, stdout, stderr))
+}
+
+/// Kills a command that timed out along with the rest of its process group
+fn kill(child: &mut Child) -> io::Result<()> {
+  if kill_group(child) {
+    return Ok(());
+  }
+  child.kill()
+}
+
+/// Kills the command's process group, whether the group had any process left to kill
+fn kill_group(child: &Child) -> bool {
+  #[cfg(target_os = "linux")]
+  {
+    // SAFETY: kill only sends a signal. The group is the child's own since it was spawned
+    // with process_group(0), and its id isn't reused while any process is left in it.
+    unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) == 0 }
+  }
+  #[cfg(not(target_os = "linux"))]
+  {
+    let _ = child;
+    false
+  }
+}
+
+/// The error of a command that didn't succeed, with the start of what it wrote to stderr
+pub(super) fn failed(status: ExitStatus, stderr: &str) -> DataCollectorError {
+  let mut stderr = stderr.trim().to_string();
+  if stderr.len() > MAX_STDERR_LENGTH {
+    let mut end = MAX_STDERR_LENGTH;
+    while !stderr.is_char_boundary(end) {
+      end -= 1;
+    }
+    stderr.truncate(end);
+  }
+  DataCollectorError::ExecFailed {
+    status: status.code(),
+    stderr,
+  }
+}
+
+/// Reads a pipe to its end, keeping the first MAX_OUTPUT_LENGTH bytes. The rest is read
+/// and dropped so the command doesn't block on a full pipe, the flag tells whether there was any.
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<(String, bool)> {
+  let (sender, receiver) = mpsc::channel();
+  thread::spawn(move || {
+    let mut output = Vec::new();
+    let mut truncated = false;
+    if let Some(mut pipe) = pipe {
+      let _ = pipe
+        .by_ref()
+        .take(MAX_OUTPUT_LENGTH as u64)
+        .read_to_end(&mut output);
+      truncated = matches!(io::copy(&mut pipe, &mut io::sink()), Ok(rest) if rest > 0);
+    }
+    let _ = sender.send((String::from_utf8_lossy(&output).to_string(), truncated));
+  });
+  receiver
+}
+
+fn wait_with_timeout(child: &mut Child, deadline: Instant) -> Result<Option<ExitStatus>> {
+  loop {
+    if let Some(status) = child.try_wait()? {
+      return Ok(Some(status));
+    }
+    if Instant::now() >= deadline {
+      return Ok(None);
+    }
+    thread::sleep(POLL_INTERVAL);
+  }
+}
+
+/// Keeps numbers and booleans as such, everything else is a string
+fn parse_value(value: &str) -> Value {
+  if let Ok(number) = value.parse::<i64>() {
+    return Value::from(number);
+  }
+  if let Ok(number) = value.parse::<f64>() {
+    if number.is_finite() {
+      return Value::from(number);
+    }
+  }
+  match value {
+    "true" => Value::Bool(true),
+    "false" => Value::Bool(false),
+    _ => Value::String(value.to_string()),
+  }
+}
+
+/// Parses `key=value` lines into an object, skipping blank lines and # comments
+fn parse_key_value(output: &str) -> Result<Value> {
+  let mut object = Map::new();
+  for line in output.lines().map(str::trim) {
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let (key, value) = line.split_once('=').ok_or_else(|| {
+      DataCollectorError::InvalidOutput(format!("expected key=value, got \"{}\"", line))
+    })?;
+    object.insert(key.trim().to_string(), parse_value(value.trim()));
+  }
+  Ok(Value::Object(object))
+}
+
+impl Collector for ExecCollector {
+  fn name(&self) -> &str {
+    &self.config.name
+  }
+
+  fn interval(&self) -> Option<f64> {
+    self.config.interval
+  }
+
+  fn timeout(&self) -> Option<f64> {
+    self.config.timeout
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let (status, stdout, stderr) = run(&self.config.command, &self.config.args, context.timeout)?;
+
+    if !status.success() {
+      return Err(failed(status, &stderr).into());
+    }
+
+    match self.config.format {
+      ExecFormat::Json => serde_json::from_str(&stdout)
+        .map_err(|e| DataCollectorError::InvalidOutput(format!("invalid JSON: {}", e)).into()),
+      ExecFormat::KeyValue => parse_key_value(&stdout),
+    }
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  fn sh(script: &str, timeout: Duration) -> Result<(ExitStatus, String, String)> {
+    run("sh", &["-c".to_string(), script.to_string()], timeout)
+  }
+
+  #[test]
+  fn kills_the_command_and_its_children_before_the_timeout() {
+    let pid_file = std::env::temp_dir().join(format!("xornet-exec-{}", std::process::id()));
+    let script = format!("sleep 10 & echo $! > {}; wait", pid_file.display());
+    let started = Instant::now();
+    let error = sh(&script, Duration::from_secs(1)).unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(matches!(
+      error.downcast_ref::<DataCollectorError>(),
+      Some(DataCollectorError::Timeout(_))
+    ));
+
+    // The orphaned sleep is either gone already or a zombie waiting to be reaped
+    thread::sleep(Duration::from_millis(100));
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    let _ = std::fs::remove_file(&pid_file);
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+    assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+  }
+
+  #[test]
+  fn doesnt_wait_for_what_the_command_left_in_the_background() {
+    let started = Instant::now();
+    let (status, stdout, _) = sh("sleep 1000 & echo x=1", Duration::from_secs(2)).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(status.success());
+    assert_eq!(stdout, "x=1\n");
+
+    // Something that left the group isn't waited for past the timeout
+    let started = Instant::now();
+    let error = sh("setsid sleep 5 & echo x=1", Duration::from_secs(1)).unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(matches!(
+      error.downcast_ref::<DataCollectorError>(),
+      Some(DataCollectorError::Timeout(_))
+    ));
+  }
+
+  #[test]
+  fn rejects_output_past_the_limit() {
+    let script = format!("head -c {} /dev/zero", MAX_OUTPUT_LENGTH + 1);
+    let error = sh(&script, Duration::from_secs(5)).unwrap_err();
+    assert!(matches!(
+      error.downcast_ref::<DataCollectorError>(),
+      Some(DataCollectorError::InvalidOutput(_))
+    ));
+
+    let script = format!("head -c {} /dev/zero", MAX_OUTPUT_LENGTH);
+    let (status, stdout, _) = sh(&script, Duration::from_secs(5)).unwrap();
+    assert!(status.success());
+    assert_eq!(stdout.len(), MAX_OUTPUT_LENGTH);
+  }
+
+  #[test]
+  fn parses_key_value_lines() {
+    let value = parse_key_value("# backups\nlast = 1650000000\nok=true\n\nname=nightly\n").unwrap();
+    assert_eq!(
+      value,
+      serde_json::json!({ "last": 1650000000, "ok": true, "name": "nightly" })
+    );
+    assert!(parse_key_value("no separator").is_err());
+  }
+}
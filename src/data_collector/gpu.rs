@@ -1,21 +1,29 @@
 use anyhow::Result;
 use nvml::NVML;
+use serde_json::Value;
 
 use crate::types::GPUStats;
 
-use super::{DataCollector, DataCollectorError};
+use super::collector::{CollectContext, Collector};
+use super::DataCollectorError;
 
 #[derive(Debug)]
 pub struct GPUFetcher {
-  pub nvidia: Option<NVML>,
+  /// NVML or why it couldn't be loaded
+  pub nvidia: std::result::Result<NVML, String>,
 }
 
-impl DataCollector {
+impl GPUFetcher {
+  pub fn new() -> Self {
+    Self {
+      nvidia: NVML::init().map_err(|e| e.to_string()),
+    }
+  }
+
   /// Get the current GPU states
   pub fn get_gpu(&mut self) -> Result<GPUStats> {
-    let gpu_fetcher = &self.gpu_fetcher;
-    match gpu_fetcher.nvidia.as_ref() {
-      Some(nvml) => {
+    match self.nvidia.as_ref() {
+      Ok(nvml) => {
         let device = nvml.device_by_index(0)?;
         let (brand, util) = (
           format!("{:?}", device.brand()?),
@@ -28,7 +36,17 @@ impl DataCollector {
           power_usage: device.power_usage()?,
         })
       }
-      None => Err(DataCollectorError::NoGPU.into())
+      Err(e) => Err(DataCollectorError::NvmlNotLoaded(e.clone()).into()),
     }
   }
 }
+
+impl Collector for GPUFetcher {
+  fn name(&self) -> &str {
+    "gpu"
+  }
+
+  fn collect(&mut self, _context: &CollectContext) -> Result<Value> {
+    Ok(serde_json::to_value(self.get_gpu()?)?)
+  }
+}
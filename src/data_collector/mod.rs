@@ -1,242 +1,441 @@
+mod block_devices;
+mod cgroups;
+mod collector;
 mod cpu;
 mod disks;
+mod environment;
+mod exec;
 mod geolocation;
 mod gpu;
 mod nics;
+mod pressure;
+mod processes;
+#[cfg(target_os = "linux")]
+mod procfs;
 mod ram;
+mod systemd_units;
 mod temps;
 mod uptimes;
 
-use crate::types::{DynamicData, StaticData};
+pub use self::collector::{CollectContext, Collector, CollectorRegistry};
+
+use crate::config_manager::{
+  CollectionConfig, CollectorsConfig, CompiledTopProcesses, ExecCollectorConfig, IntervalsConfig,
+  TimeoutsConfig,
+};
+use crate::filter::CompiledFilters;
+use crate::scheduler::CollectorSchedule;
+use crate::types::{CollectorError, DynamicData, StaticData};
 use anyhow::{anyhow, Result};
-use nvml::NVML;
-use std::{collections::HashMap, time::SystemTime};
-use sysinfo::{ProcessRefreshKind, ProcessorExt, System, SystemExt};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::Arc,
+  time::{Duration, SystemTime},
+};
+use sysinfo::{ProcessExt, ProcessorExt, System, SystemExt};
 use thiserror::Error;
+use tokio::time::Instant;
 
-use self::gpu::GPUFetcher;
-
-#[cfg(target_family = "windows")]
-use windows::Win32::System::Performance::*;
-
-#[cfg(target_family = "windows")]
-use std::ptr;
-
-#[cfg(target_family = "windows")]
-use std::thread::sleep;
-
-#[cfg(target_family = "windows")]
-use windows::core::PCSTR;
+#[derive(Error, Debug)]
+pub enum DataCollectorError {
+  #[error("NVML not loaded: {0}")]
+  NvmlNotLoaded(String),
+  #[error("No temperature sensors found")]
+  NoSensors,
+  #[error("Pressure stall information isn't available")]
+  NoPressure,
+  #[error("systemd isn't running")]
+  NoSystemd,
+  /// Returned by Linux only collectors on other platforms
+  #[error("Not supported on this platform")]
+  Unsupported,
+  #[error("Timed out after {0}s")]
+  Timeout(f64),
+  #[error("Still running since an earlier tick")]
+  StillRunning,
+  #[error("Collector panicked: {0}")]
+  Panicked(String),
+  #[error("Command exited with {}: {stderr}", exit_status_text(.status))]
+  ExecFailed { status: Option<i32>, stderr: String },
+  #[error("Invalid output: {0}")]
+  InvalidOutput(String),
+}
 
-#[cfg(target_family = "windows")]
-use windows::Win32::Foundation::ERROR_SUCCESS;
+fn exit_status_text(status: &Option<i32>) -> String {
+  match status {
+    Some(code) => format!("status {}", code),
+    None => "a signal".to_string(),
+  }
+}
 
-#[cfg(target_family = "windows")]
-use std::process::exit;
+impl DataCollectorError {
+  /// A stable code for the backend to tell the reasons apart by
+  pub fn code(&self) -> &'static str {
+    match self {
+      DataCollectorError::NvmlNotLoaded(_) => "nvml_not_loaded",
+      DataCollectorError::NoSensors => "no_sensors",
+      DataCollectorError::NoPressure => "no_pressure",
+      DataCollectorError::NoSystemd => "no_systemd",
+      DataCollectorError::Unsupported => "unsupported",
+      DataCollectorError::Timeout(_) => "timeout",
+      DataCollectorError::StillRunning => "still_running",
+      DataCollectorError::Panicked(_) => "panicked",
+      DataCollectorError::ExecFailed { .. } => "exec_failed",
+      DataCollectorError::InvalidOutput(_) => "invalid_output",
+    }
+  }
 
-#[derive(Error, Debug)]
-pub enum DataCollectorError {
-  #[error("GPU usage unavailable")]
-  NoGPU,
-  #[error("Temperature unavailable")]
-  NoTemp,
+  /// Whether the metric just doesn't exist on this machine rather than something going wrong
+  pub fn is_unavailable(&self) -> bool {
+    matches!(
+      self,
+      DataCollectorError::NvmlNotLoaded(_)
+        | DataCollectorError::NoSensors
+        | DataCollectorError::NoPressure
+        | DataCollectorError::NoSystemd
+        | DataCollectorError::Unsupported
+    )
+  }
 }
 
-#[cfg(target_family = "unix")]
-#[derive(Debug)]
-pub struct DataCollector {
-  pub gpu_fetcher: GPUFetcher,
-  pub fetcher: System,
-  pub program_iterations: usize,
-  iterator_index: usize,
-  network_interface_speeds: HashMap<String, f32>,
-  start_timestamp: u128,
+impl CollectorError {
+  fn from_error(error: &anyhow::Error) -> Self {
+    let data_collector_error = error.downcast_ref::<DataCollectorError>();
+    let (exit_status, stderr) = match data_collector_error {
+      Some(DataCollectorError::ExecFailed { status, stderr }) => (*status, Some(stderr.clone())),
+      _ => (None, None),
+    };
+
+    Self {
+      code: data_collector_error
+        .map(DataCollectorError::code)
+        .unwrap_or("failed")
+        .to_string(),
+      message: error.to_string(),
+      exit_status,
+      stderr,
+    }
+  }
 }
 
-#[cfg(target_family = "windows")]
 #[derive(Debug)]
 pub struct DataCollector {
-  pub gpu_fetcher: GPUFetcher,
+  /// Used for the static data, uptime and the reporter's own process,
+  /// the collectors each have their own so they can run at the same time
   pub fetcher: System,
   pub program_iterations: usize,
-  pub pdh_query: isize,
-  pub pdh_proc_perf_counter: isize,
-  pub pdh_proc_freq_counter: isize,
-  pub pdh_proc_util_counter: isize,
-  pub pdh_proc_perf_data_cached: *mut PDH_FMT_COUNTERVALUE_ITEM_A,
-  pub pdh_proc_perf_data_len: usize,
-  pub pdh_proc_perf_data_capacity: usize,
-  pub pdh_proc_freq_data_cached: *mut PDH_FMT_COUNTERVALUE_ITEM_A,
-  pub pdh_proc_freq_data_len: usize,
-  pub pdh_proc_freq_data_capacity: usize,
-  pub pdh_proc_util_data_cached: *mut PDH_FMT_COUNTERVALUE_ITEM_A,
-  pub pdh_proc_util_data_len: usize,
-  pub pdh_proc_util_data_capacity: usize,
-  pub first_pdh_called: bool,
+  pub collectors: CollectorsConfig,
+  pub intervals: IntervalsConfig,
+  pub timeouts: TimeoutsConfig,
+  pub filters: Arc<CompiledFilters>,
+  pub top_processes: Arc<CompiledTopProcesses>,
+  /// Handed to the collectors for the settings that are only theirs
+  pub collection: Arc<CollectionConfig>,
+  pub registry: CollectorRegistry,
+  /// The exec collectors from the config, replaced in the registry when it changes
+  exec: Vec<ExecCollectorConfig>,
+  pub schedule: CollectorSchedule,
+  /// How long each of the collectors that ran in the last get_all_dynamic_data took
+  pub last_collection_times: BTreeMap<String, Duration>,
+  /// What each collector returned last, sent again for collectors that aren't due yet
+  last_values: BTreeMap<String, Value>,
+  last_errors: BTreeMap<String, CollectorError>,
   iterator_index: usize,
-  network_interface_speeds: HashMap<String, f32>,
-  start_timestamp: u128
+  /// Bumped whenever the lists should be refreshed, see list_refresh_due
+  list_generation: u64,
+  list_generations: HashMap<String, u64>,
+  /// Whether the names in the config were checked against the registry since it was applied
+  names_checked: bool,
+  start_timestamp: u128,
 }
 
-
 impl DataCollector {
   /// Creates a new data collector
-  #[cfg(target_family = "unix")]
-  pub fn new() -> Result<Self> {
-    let (fetcher, gpu_fetcher) = (
-      System::new_all(),
-      GPUFetcher {
-        nvidia: NVML::init().ok(),
-      }
-    );
-
-    Ok(Self {
-      gpu_fetcher,
-      fetcher,
+  pub fn new(collection: &CollectionConfig) -> Result<Self> {
+    let mut data_collector = Self {
+      fetcher: System::new_all(),
+      program_iterations: collection.program_iterations,
+      collectors: collection.collectors.clone(),
+      intervals: collection.intervals.clone(),
+      timeouts: collection.timeouts.clone(),
+      filters: Arc::new(collection.filters.compile()?),
+      top_processes: Arc::new(collection.top_processes.compile()?),
+      collection: Arc::new(collection.clone()),
+      registry: CollectorRegistry::with_builtins()?,
+      exec: Vec::new(),
+      schedule: CollectorSchedule::default(),
+      last_collection_times: BTreeMap::new(),
+      last_values: BTreeMap::new(),
+      last_errors: BTreeMap::new(),
       iterator_index: 0,
-      program_iterations: 60,
-      network_interface_speeds: HashMap::new(),
+      list_generation: 0,
+      list_generations: HashMap::new(),
+      names_checked: false,
       start_timestamp: SystemTime::now()
-          .duration_since(SystemTime::UNIX_EPOCH)?
-          .as_millis(),
-    })
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis(),
+    };
+    data_collector.register_exec_collectors(&collection.exec)?;
+    Ok(data_collector)
   }
 
-  /// Creates a new data collector but initializing Windows centric state.
-  #[cfg(target_family = "windows")]
-  pub fn new() -> Result<Self> {
-    let (fetcher, gpu_fetcher) = (
-      System::new_all(),
-      GPUFetcher {
-        nvidia: NVML::init().ok(),
-      }
-    );
-
-    let mut pdh_query = 0 as isize;
-    let mut pdh_proc_perf_counter = 0 as isize;
-    let mut pdh_proc_freq_counter = 0 as isize;
-    let mut pdh_proc_util_counter = 0 as isize;
+  /// Replaces the registered exec collectors with the ones in the config
+  fn register_exec_collectors(&mut self, exec: &[ExecCollectorConfig]) -> Result<()> {
+    if self.exec == exec {
+      return Ok(());
+    }
 
-    let pdh_proc_perf_data_cached = ptr::null_mut();
-    let pdh_proc_freq_data_cached = ptr::null_mut();
-    let pdh_proc_util_data_cached = ptr::null_mut();
+    // Checked first so a clash doesn't leave the old exec collectors half replaced
+    for config in exec {
+      let current = self.exec.iter().any(|old| old.name == config.name);
+      if !current && self.registry.get(&config.name).is_some() {
+        return Err(anyhow!(
+          "The exec collector \"{}\" has the name of a built-in collector",
+          config.name
+        ));
+      }
+    }
 
-    unsafe {
-      // Establishes our means to query the winapi performance monitoring system.
-      let ret = PdhOpenQueryA(PCSTR::default(), 0, &mut pdh_query as *mut isize);
+    for config in &self.exec {
+      self.registry.unregister(&config.name);
+    }
+    for config in exec {
+      self
+        .registry
+        .register(Box::new(exec::ExecCollector::new(config.clone())))?;
+    }
+    self.exec = exec.to_vec();
+    Ok(())
+  }
 
-      if ret != ERROR_SUCCESS.0 as i32 {
-        eprintln!("Unable to open perfmon query. Errno: {}", ret);
-        exit(ret);
-      }
+  /// Applies new collection settings, e.g. after the config was reloaded
+  pub fn apply_config(&mut self, collection: &CollectionConfig) -> Result<()> {
+    self.filters = Arc::new(collection.filters.compile()?);
+    self.top_processes = Arc::new(collection.top_processes.compile()?);
+    self.collection = Arc::new(collection.clone());
+    self.collectors = collection.collectors.clone();
+    self.program_iterations = collection.program_iterations;
+    self.intervals = collection.intervals.clone();
+    self.timeouts = collection.timeouts.clone();
+    self.register_exec_collectors(&collection.exec)?;
+
+    // Start over so everything gets collected with the new settings right away
+    self.iterator_index = 0;
+    self.list_generation += 1;
+    self.names_checked = false;
+    self.schedule.reset();
+    Ok(())
+  }
 
-      // Metric to determine per logical CPU speed relative to base clock of CPU.
-      // Example: CPU base frequency is 3500 Mhz and processor performance is 106.
-      //          Performance value should be interpreted as a percentage, so in this case
-      //          base_freq * (performance / 100) = 3710 Mhz.
-      let ret = PdhAddEnglishCounterA(pdh_query,
-                                      "\\Processor Information(*)\\% Processor Performance",
-                                      0,
-                                      &mut pdh_proc_perf_counter as *mut isize);
-
-      if ret != ERROR_SUCCESS.0 as i32 {
-        eprintln!("Unable to add processor performance counter to perfmon query. Errno: {}", ret);
-        exit(ret);
-      }
+  /// Increments the iterator index by one or resets it to 0 if it reaches the program iterations
+  pub fn increment_iterator_index(&mut self) {
+    self.iterator_index += 1;
+    if self.program_iterations <= self.iterator_index {
+      self.iterator_index = 0;
+      self.list_generation += 1;
+    }
+  }
 
-      // Do I really have to say it?
-      let ret = PdhAddEnglishCounterA(pdh_query,
-                                      "\\Processor Information(*)\\Processor Frequency",
-                                      0,
-                                      &mut pdh_proc_freq_counter as *mut isize);
+  /// Whether a list like the disks or network interfaces should be refreshed on this run.
+  /// Lists are refreshed once every program iterations, on the first run of their collector
+  /// after that since collectors with their own interval don't run on every tick.
+  fn list_refresh_due(&mut self, collector: &str) -> bool {
+    let generation = self.list_generation;
+    self
+      .list_generations
+      .insert(collector.to_string(), generation)
+      != Some(generation)
+  }
 
-      if ret != ERROR_SUCCESS.0 as i32 {
-        eprintln!("Unable to add processor frequency counter to perfmon query. Errno: {}", ret);
-        exit(ret);
-      }
+  /// Logs a collector's failure when it's different from the last one,
+  /// so a missing GPU isn't reported on every tick
+  fn log_failure(&self, name: &str, e: &anyhow::Error, error: &CollectorError) {
+    if self.last_errors.get(name) == Some(error) {
+      return;
+    }
 
-      // Processor utilization per core.
-      let ret = PdhAddEnglishCounterA(pdh_query,
-                                      "\\Processor Information(*)\\% Processor Utility",
-                                      0,
-                                      &mut pdh_proc_util_counter as *mut isize);
+    let unavailable = matches!(
+      e.downcast_ref::<DataCollectorError>(),
+      Some(error) if error.is_unavailable()
+    );
+    if unavailable {
+      debug!(collector = name, code = error.code; "No {} data: {}", name, e);
+    } else {
+      warn!(collector = name, code = error.code; "The {} collector failed: {}", name, e);
+    }
+  }
 
-      if ret != ERROR_SUCCESS.0 as i32 {
-        eprintln!("Unable to add processor performance utilization to perfmon query. Errno: {}", ret);
-        exit(ret);
+  /// Warns about collector names in the config that no collector is registered under, which
+  /// are likely typos. Done on the first run rather than on creation so that collectors
+  /// registered after it count too.
+  fn warn_unknown_names(&self) {
+    let names = (self
+      .collectors
+      .collectors
+      .keys()
+      .map(|name| ("collectors", name)))
+    .chain(
+      self
+        .intervals
+        .collectors
+        .keys()
+        .map(|name| ("intervals", name)),
+    )
+    .chain(
+      self
+        .timeouts
+        .collectors
+        .keys()
+        .map(|name| ("timeouts", name)),
+    );
+    for (section, name) in names {
+      if self.registry.get(name).is_none() {
+        warn!(
+          "collection.{}.{} doesn't match any collector, it's ignored",
+          section, name
+        );
       }
+    }
+  }
 
-      // We have to prime the query data with an initial point of reference. If you
-      // don't do this the data retrieved the first time around will either be zeroed or
-      // garbage data that won't be useful to you.
-      let ret = PdhCollectQueryData(pdh_query);
-
-      if ret != ERROR_SUCCESS.0 as i32 {
-        eprintln!("Unable to initialize perfmon queries. Errno: {}", ret);
-        exit(ret);
+  /// Takes a built-in collector's value out of the collected values. One that doesn't have
+  /// the built-in's shape, like from a collector registered in its place, is left out with
+  /// an invalid_output error.
+  fn take<T: DeserializeOwned>(
+    &self,
+    values: &mut BTreeMap<String, Value>,
+    errors: &mut BTreeMap<String, CollectorError>,
+    name: &str,
+  ) -> Option<T> {
+    let value = values.remove(name)?;
+    match serde_json::from_value(value) {
+      Ok(value) => Some(value),
+      Err(e) => {
+        let e = DataCollectorError::InvalidOutput(e.to_string()).into();
+        let error = CollectorError::from_error(&e);
+        self.log_failure(name, &e, &error);
+        errors.insert(name.to_string(), error);
+        None
       }
+    }
+  }
 
-      // Guarantees the next invocation of PdhCollectQueryData will yield valid data.
-      sleep(std::time::Duration::from_millis(1000));
+  /// Runs every collector that's due at the same time, each on a thread of the blocking pool.
+  /// A collector that doesn't finish within its timeout or fails is left out of the sample
+  /// with the reason in its errors, collectors that weren't due keep their last value.
+  /// A collector that's still running from an earlier tick isn't started again, so a hung
+  /// one only ever holds up a single thread.
+  pub async fn get_all_dynamic_data(&mut self) -> Result<DynamicData> {
+    if !self.names_checked {
+      self.warn_unknown_names();
+      self.names_checked = true;
     }
 
+    let registered: Vec<_> = self.registry.iter().cloned().collect();
+    let mut values = BTreeMap::new();
+    let mut errors = BTreeMap::new();
+    let mut runs = Vec::new();
 
-    Ok(Self {
-      gpu_fetcher,
-      fetcher,
-      pdh_query,
-      pdh_proc_perf_counter,
-      pdh_proc_freq_counter,
-      pdh_proc_util_counter,
-      pdh_proc_perf_data_cached,
-      pdh_proc_perf_data_len: 0,
-      pdh_proc_perf_data_capacity: 0,
-      pdh_proc_freq_data_cached,
-      pdh_proc_freq_data_len: 0,
-      pdh_proc_freq_data_capacity: 0,
-      pdh_proc_util_data_cached,
-      pdh_proc_util_data_len: 0,
-      pdh_proc_util_data_capacity: 0,
-      first_pdh_called: false,
-      iterator_index: 0,
-      program_iterations: 60,
-      network_interface_speeds: HashMap::new(),
-      start_timestamp: SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)?
-        .as_millis(),
-    })
-  }
+    for entry in registered {
+      if !self.collectors.enabled(&entry.name, entry.enabled) {
+        continue;
+      }
 
-  /// Increments the iterator index by one or resets it to 0 if it reaches the program iterations
-  pub fn increment_iterator_index(&mut self) {
-    self.iterator_index += 1;
-    if self.program_iterations <= self.iterator_index {
-      self.iterator_index = 0;
+      let interval = self.intervals.get(&entry.name).or(entry.interval);
+      if !self.schedule.is_due(&entry.name, interval) {
+        if let Some(value) = self.last_values.get(&entry.name) {
+          values.insert(entry.name.clone(), value.clone());
+        }
+        // Keep reporting why it's missing until it runs again
+        if let Some(error) = self.last_errors.get(&entry.name) {
+          errors.insert(entry.name.clone(), error.clone());
+        }
+        continue;
+      }
+
+      let timeout = self
+        .timeouts
+        .of(self.timeouts.get(&entry.name).or(entry.timeout));
+      let context = CollectContext {
+        filters: self.filters.clone(),
+        top_processes: self.top_processes.clone(),
+        collection: self.collection.clone(),
+        refresh_lists: self.list_refresh_due(&entry.name),
+        timeout,
+      };
+      let collector = entry.collector.clone();
+      let task = tokio::task::spawn_blocking(move || {
+        let mut collector = collector
+          .try_lock()
+          .ok_or(DataCollectorError::StillRunning)?;
+        collector.collect(&context)
+      });
+      runs.push((entry.name, interval, Instant::now(), timeout, task));
     }
-  }
 
-  pub fn get_all_dynamic_data(&mut self) -> Result<DynamicData> {
-    #[cfg(target_family = "windows")]
-    unsafe {
-      let ret = PdhCollectQueryData(self.pdh_query);
-      if ret != ERROR_SUCCESS.0 as i32 {
-        eprintln!("Unable to query latest CPU information from perfmon.");
-        exit(1);
+    let mut times = BTreeMap::new();
+    for (name, interval, started, timeout, task) in runs {
+      let result = match tokio::time::timeout_at(started + timeout, task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(DataCollectorError::Panicked(e.to_string()).into()),
+        Err(_) => Err(DataCollectorError::Timeout(timeout.as_secs_f64()).into()),
+      };
+      times.insert(name.clone(), started.elapsed());
+
+      match result {
+        Ok(value) => {
+          self.schedule.mark_run(&name, interval);
+          values.insert(name, value);
+        }
+        Err(e) => {
+          let error = CollectorError::from_error(&e);
+          self.log_failure(&name, &e, &error);
+          errors.insert(name, error);
+        }
       }
     }
 
-    Ok(DynamicData {
-      cpu: self.get_cpu()?,
-      ram: self.get_ram()?,
-      swap: self.get_swap()?,
-      gpu: self.get_gpu().ok(),
-      process_count: self.get_total_process_count()? as i32,
-      disks: self.get_disks()?,
-      temps: self.get_temps().ok(),
-      network: self.get_network()?,
-      host_uptime: self.get_uptime()?,
-      reporter_uptime: self.get_reporter_uptime()?,
-    })
+    self.last_collection_times = times;
+    self.last_values = values.clone();
+
+    let mut dynamic_data = DynamicData {
+      cpu: self.take(&mut values, &mut errors, "cpu"),
+      ram: self.take(&mut values, &mut errors, "ram"),
+      swap: self.take(&mut values, &mut errors, "swap"),
+      gpu: self.take(&mut values, &mut errors, "gpu"),
+      process_count: self.take(&mut values, &mut errors, "processes"),
+      disks: self.take(&mut values, &mut errors, "disks"),
+      network_disks: self.take(&mut values, &mut errors, "network_disks"),
+      temps: self.take(&mut values, &mut errors, "temps"),
+      network: self.take(&mut values, &mut errors, "network"),
+      top_processes: self.take(&mut values, &mut errors, "top_processes"),
+      pressure: self.take(&mut values, &mut errors, "pressure"),
+      cgroups: self.take(&mut values, &mut errors, "cgroups"),
+      block_devices: self.take(&mut values, &mut errors, "block_devices"),
+      systemd: self.take(&mut values, &mut errors, "systemd"),
+      host_uptime: self.get_uptime(),
+      reporter_uptime: self.get_reporter_uptime(),
+      // Filled in by the reporter which keeps track of its own stats
+      reporter: None,
+      errors: BTreeMap::new(),
+      extra: values,
+    };
+    self.last_errors = errors.clone();
+    dynamic_data.errors = errors;
+    Ok(dynamic_data)
+  }
+
+  /// Gets the memory in KB and the CPU usage of the reporter's own process
+  pub fn get_reporter_process(&mut self) -> Result<(u64, f32)> {
+    let pid = sysinfo::get_current_pid().map_err(|e| anyhow!(e))?;
+    self.fetcher.refresh_process(pid);
+    let process = self
+      .fetcher
+      .process(pid)
+      .ok_or_else(|| anyhow!("Could not find the reporter's own process"))?;
+    Ok((process.memory(), process.cpu_usage()))
   }
 
   /// Gets the hostname of the system
@@ -248,19 +447,69 @@ impl DataCollector {
     ))
   }
 
-  /// Gets the total amount of processes running
-  pub fn get_total_process_count(&mut self) -> Result<usize> {
-    self
-      .fetcher
-      .refresh_processes_specifics(ProcessRefreshKind::new());
-    return Ok(self.fetcher.processes().len());
+  /// Gets the static information of the collectors that have any, skipping the ones that
+  /// are busy. Like collect, static_info runs on the blocking pool within the collector's timeout.
+  pub async fn get_collector_statics(&self) -> BTreeMap<String, Value> {
+    let mut runs = Vec::new();
+    for entry in self.registry.iter() {
+      if !self.collectors.enabled(&entry.name, entry.enabled) {
+        continue;
+      }
+
+      let timeout = self
+        .timeouts
+        .of(self.timeouts.get(&entry.name).or(entry.timeout));
+      let collector = entry.collector.clone();
+      let task = tokio::task::spawn_blocking(move || match collector.try_lock() {
+        Some(mut collector) => collector.static_info(),
+        None => Ok(None),
+      });
+      runs.push((entry.name.clone(), Instant::now(), timeout, task));
+    }
+
+    let mut statics = BTreeMap::new();
+    for (name, started, timeout, task) in runs {
+      let result = match tokio::time::timeout_at(started + timeout, task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(DataCollectorError::Panicked(e.to_string()).into()),
+        Err(_) => Err(DataCollectorError::Timeout(timeout.as_secs_f64()).into()),
+      };
+      match result {
+        Ok(Some(info)) => {
+          statics.insert(name, info);
+        }
+        Ok(None) => {}
+        Err(e) => warn!(
+          "Could not get the static info of the {} collector: {}",
+          name, e
+        ),
+      }
+    }
+    statics
+  }
+
+  /// Gets the total memory in KB, the container's limit when scoped to it
+  fn get_total_memory(&self) -> u64 {
+    let total = self.fetcher.total_memory();
+    if !self.collection.container.scope_to_cgroup {
+      return total;
+    }
+    match environment::container_memory(&self.collection.roots) {
+      Some((_, Some(limit))) => total.min(limit / 1000),
+      _ => total,
+    }
   }
 
   /// Gets all the static information about the system
   /// that can't change in runtime
   pub async fn get_statics(&self) -> Result<StaticData> {
     let processor_info = self.fetcher.global_processor_info();
-    let geolocation_stuff = DataCollector::get_geolocation_info().await;
+    let timeout = self.timeouts.of(self.timeouts.statics);
+    let geolocation_stuff =
+      match tokio::time::timeout(timeout, DataCollector::get_geolocation_info()).await {
+        Ok(geolocation_stuff) => geolocation_stuff,
+        Err(_) => Err(DataCollectorError::Timeout(timeout.as_secs_f64()).into()),
+      };
 
     if geolocation_stuff.is_err() {
       return Ok(StaticData {
@@ -275,8 +524,11 @@ impl DataCollector {
         os_name: self.fetcher.name(),
         cpu_cores: self.fetcher.physical_core_count(),
         cpu_threads: self.fetcher.processors().len(),
-        total_mem: self.fetcher.total_memory(),
+        total_mem: self.get_total_memory(),
         reporter_version: env!("CARGO_PKG_VERSION").to_string(),
+        container: environment::detect_container(&self.collection.roots),
+        virtualization: environment::detect_virtualization(&self.collection.roots),
+        extra: self.get_collector_statics().await,
       });
     }
 
@@ -294,8 +546,11 @@ impl DataCollector {
       os_name: self.fetcher.name(),
       cpu_cores: self.fetcher.physical_core_count(),
       cpu_threads: self.fetcher.processors().len(),
-      total_mem: self.fetcher.total_memory(),
+      total_mem: self.get_total_memory(),
       reporter_version: env!("CARGO_PKG_VERSION").to_string(),
+      container: environment::detect_container(&self.collection.roots),
+      virtualization: environment::detect_virtualization(&self.collection.roots),
+      extra: self.get_collector_statics().await,
     });
   }
 }
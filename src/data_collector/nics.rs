@@ -1,12 +1,22 @@
+use crate::config_manager::RootsConfig;
+use crate::filter::CompiledFilters;
 use crate::types::NetworkInterfaceStats;
 use crate::util::parse_speed;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
 use std::process::Command;
 use std::str::FromStr;
 
 use std::env;
-use sysinfo::{NetworkExt, SystemExt};
+use sysinfo::{NetworkExt, System, SystemExt};
+
+use super::collector::{CollectContext, Collector};
+
+#[cfg(target_os = "linux")]
+use super::procfs::{self, CounterHistory};
 
 #[allow(non_snake_case)] // https://github.com/xornet-cloud/Reporter/pull/24#pullrequestreview-927780254
 #[derive(Serialize, Deserialize, Debug)]
@@ -15,39 +25,59 @@ pub struct WindowsNetworkInterface {
   pub LinkSpeed: String,
 }
 
-use super::DataCollector;
+/// Keeps the network interfaces and their link speeds between runs
+#[derive(Debug, Default)]
+pub struct NetworkCollector {
+  system: System,
+  interface_speeds: HashMap<String, f32>,
+  /// The bytes received and sent by every interface of another procfs like the host's,
+  /// whose interfaces sysinfo can't see
+  #[cfg(target_os = "linux")]
+  history: CounterHistory<HashMap<String, (u64, u64)>>,
+}
 
-impl DataCollector {
-  /// Gets the current network stats
+impl NetworkCollector {
+  /// Gets the current network stats, along with the link speeds when refreshing the list
+  pub fn get_network(
+    &mut self,
+    filters: &CompiledFilters,
+    refresh_list: bool,
+    roots: &RootsConfig,
+  ) -> Result<Vec<NetworkInterfaceStats>> {
+    #[cfg(target_os = "linux")]
+    if !roots.is_own_proc() {
+      return self.get_other_network(filters, refresh_list, roots);
+    }
 
-  pub fn get_network(&mut self) -> Result<Vec<NetworkInterfaceStats>> {
     let mut nics = Vec::new();
 
-    if self.iterator_index == 0 {
-      self.fetcher.refresh_networks_list();
+    if refresh_list {
+      debug!("Refreshing the network interface list");
+      self.system.refresh_networks_list();
     } else {
-      self.fetcher.refresh_networks();
+      self.system.refresh_networks();
     }
 
-    let nicspeeds = if self.iterator_index == 0 && env::consts::OS == "windows" {
-      DataCollector::get_nic_linkspeeds()?
+    // Without the link speeds the interfaces are still sent, with a speed of 0
+    let nicspeeds = if refresh_list && env::consts::OS == "windows" {
+      NetworkCollector::get_nic_linkspeeds().unwrap_or_else(|e| {
+        debug!("Could not get the link speeds: {}", e);
+        vec![]
+      })
     } else {
       vec![]
     };
 
-    for (interface_name, data) in self.fetcher.networks() {
-      // Ignore bullshit loopback interfaces, no one cares
-      if interface_name.contains("NPCAP")
-        || interface_name.starts_with("lo")
-        || interface_name.starts_with("loopback")
-      {
+    for (interface_name, data) in self.system.networks() {
+      // Skip filtered interfaces, by default the bullshit loopback ones no one cares about
+      if !filters.nics.allows(interface_name) {
         continue;
       };
 
-      if self.iterator_index == 0 {
+      if refresh_list {
         // Get the speed of the interface on linux otherwise it's 0
         let speed = match env::consts::OS {
-          "linux" => DataCollector::get_nic_linkspeed(interface_name)?,
+          "linux" => NetworkCollector::get_nic_linkspeed(roots, interface_name),
           "windows" => {
             let nic_index = nicspeeds
               .iter()
@@ -61,8 +91,9 @@ impl DataCollector {
           }
           _ => 0.0,
         };
+        trace!("Link speed of {} is {}Mbps", interface_name, speed);
         self
-          .network_interface_speeds
+          .interface_speeds
           .insert(interface_name.to_string(), speed);
       }
 
@@ -71,7 +102,7 @@ impl DataCollector {
         tx: data.transmitted() * 8,
         rx: data.received() * 8,
         s: self
-          .network_interface_speeds
+          .interface_speeds
           .get(&interface_name.to_string())
           .unwrap_or(&0.0)
           .to_owned(),
@@ -83,13 +114,54 @@ impl DataCollector {
     Ok(nics)
   }
 
-  fn get_nic_linkspeed(interface_name: &str) -> Result<f32> {
-    let interface_path = format!("/sys/class/net/{}/speed", interface_name);
-    let interface_speed = Command::new("cat").arg(&interface_path).output()?;
-    let interface_speed =
-      f32::from_str(&String::from_utf8_lossy(&interface_speed.stdout).replace("\n", ""))
-        .unwrap_or(0.0);
-    Ok(interface_speed)
+  /// Gets the network stats of another procfs like the host's from its init's net/dev,
+  /// since sysinfo only sees the interfaces of the reporter's own network namespace
+  #[cfg(target_os = "linux")]
+  fn get_other_network(
+    &mut self,
+    filters: &CompiledFilters,
+    refresh_list: bool,
+    roots: &RootsConfig,
+  ) -> Result<Vec<NetworkInterfaceStats>> {
+    let counters = procfs::read_net_dev(roots)?;
+    let delta = self.history.start();
+
+    let mut nics = Vec::new();
+    for (interface_name, current) in &counters {
+      if !filters.nics.allows(interface_name) {
+        continue;
+      }
+
+      if refresh_list || !self.interface_speeds.contains_key(interface_name) {
+        let speed = NetworkCollector::get_nic_linkspeed(roots, interface_name);
+        self.interface_speeds.insert(interface_name.clone(), speed);
+      }
+
+      // Like sysinfo's, the bytes since the last run, 0 on the first run
+      let (received, sent) = delta.last_of(interface_name, current);
+      nics.push(NetworkInterfaceStats {
+        n: interface_name.clone(),
+        tx: current.1.saturating_sub(*sent) * 8,
+        rx: current.0.saturating_sub(*received) * 8,
+        s: self.interface_speeds[interface_name],
+      });
+    }
+
+    self.history.finish(counters);
+    Ok(nics)
+  }
+
+  /// The link speed in Mbps, 0 when the interface doesn't have one like a bridge
+  /// or a virtual interface, whose speed file can't be read while it's down
+  fn get_nic_linkspeed(roots: &RootsConfig, interface_name: &str) -> f32 {
+    let interface_path = roots.sys(&format!("class/net/{}/speed", interface_name));
+    match fs::read_to_string(&interface_path) {
+      Ok(speed) => f32::from_str(speed.trim()).unwrap_or(0.0),
+      Err(e) => {
+        trace!("Could not read the link speed of {}: {}", interface_name, e);
+        0.0
+      }
+    }
   }
 
   fn get_nic_linkspeeds() -> Result<Vec<(String, f32)>> {
@@ -122,3 +194,18 @@ impl DataCollector {
     Ok(nics)
   }
 }
+
+impl Collector for NetworkCollector {
+  fn name(&self) -> &str {
+    "network"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let network = self.get_network(
+      &context.filters,
+      context.refresh_lists,
+      &context.collection.roots,
+    )?;
+    Ok(serde_json::to_value(network)?)
+  }
+}
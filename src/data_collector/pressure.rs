@@ -0,0 +1,77 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::config_manager::RootsConfig;
+use crate::types::PressureStats;
+
+use super::collector::{CollectContext, Collector};
+
+#[cfg(target_os = "linux")]
+use super::procfs;
+
+#[cfg(target_os = "linux")]
+use crate::types::CgroupPressure;
+
+#[cfg(target_os = "linux")]
+use std::collections::BTreeMap;
+
+use super::DataCollectorError;
+
+#[derive(Debug)]
+pub struct PressureCollector;
+
+impl PressureCollector {
+  /// Gets the pressure of the whole system and the cgroups matching the patterns
+  #[cfg(target_os = "linux")]
+  pub fn get_pressure(&self, cgroups: &[String], roots: &RootsConfig) -> Result<PressureStats> {
+    let system = roots.proc("pressure");
+    let cpu = procfs::read_pressure(&system.join("cpu"));
+    let memory = procfs::read_pressure(&system.join("memory"));
+    let io = procfs::read_pressure(&system.join("io"));
+
+    // Kernels before 4.20 or booted with psi=0 don't have any of them
+    if cpu.is_none() && memory.is_none() && io.is_none() {
+      return Err(DataCollectorError::NoPressure.into());
+    }
+
+    let mut stats = BTreeMap::new();
+    if let Some(root) = procfs::cgroup2_root(roots) {
+      for pattern in cgroups {
+        for (name, path) in procfs::find_cgroups(&root, pattern) {
+          let pressure = CgroupPressure {
+            cpu: procfs::read_pressure(&path.join("cpu.pressure")),
+            memory: procfs::read_pressure(&path.join("memory.pressure")),
+            io: procfs::read_pressure(&path.join("io.pressure")),
+          };
+          if pressure.cpu.is_some() || pressure.memory.is_some() || pressure.io.is_some() {
+            stats.insert(name, pressure);
+          }
+        }
+      }
+    }
+
+    Ok(PressureStats {
+      cpu,
+      memory,
+      io,
+      cgroups: stats,
+    })
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  pub fn get_pressure(&self, _cgroups: &[String], _roots: &RootsConfig) -> Result<PressureStats> {
+    Err(DataCollectorError::NoPressure.into())
+  }
+}
+
+impl Collector for PressureCollector {
+  fn name(&self) -> &str {
+    "pressure"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let collection = &context.collection;
+    let pressure = self.get_pressure(&collection.pressure.cgroups, &collection.roots)?;
+    Ok(serde_json::to_value(pressure)?)
+  }
+}
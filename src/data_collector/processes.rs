@@ -0,0 +1,179 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashSet;
+use std::time::{Instant, SystemTime};
+use sysinfo::{AsU32, Pid, Process, ProcessExt, ProcessRefreshKind, System, SystemExt};
+
+use crate::config_manager::{CompiledTopProcesses, ProcessSort};
+use crate::types::ProcessStats;
+
+use super::collector::{CollectContext, Collector};
+
+#[derive(Debug, Default)]
+pub struct ProcessCountCollector {
+  system: System,
+}
+
+impl ProcessCountCollector {
+  /// Gets the total amount of processes running
+  pub fn get_process_count(&mut self) -> Result<usize> {
+    self
+      .system
+      .refresh_processes_specifics(ProcessRefreshKind::new());
+    Ok(self.system.processes().len())
+  }
+}
+
+impl Collector for ProcessCountCollector {
+  fn name(&self) -> &str {
+    "processes"
+  }
+
+  fn collect(&mut self, _context: &CollectContext) -> Result<Value> {
+    Ok(serde_json::to_value(self.get_process_count()?)?)
+  }
+}
+
+#[derive(Debug)]
+pub struct TopProcessesCollector {
+  system: System,
+  /// When the processes were last refreshed, their disk usage is counted since then
+  last_refresh: Option<Instant>,
+  /// The processes of the last run, the disk usage of new ones covers their whole life
+  /// so they're left at 0 until the next run
+  known: HashSet<Pid>,
+}
+
+impl TopProcessesCollector {
+  pub fn new() -> Self {
+    let mut system = System::new();
+    system.refresh_users_list();
+    Self {
+      system,
+      last_refresh: None,
+      known: HashSet::new(),
+    }
+  }
+
+  /// Gets the processes using the most of what the settings sort by
+  pub fn get_top_processes(
+    &mut self,
+    settings: &CompiledTopProcesses,
+    refresh_users: bool,
+  ) -> Result<Vec<ProcessStats>> {
+    if refresh_users {
+      self.system.refresh_users_list();
+    }
+    self.system.refresh_processes();
+
+    // sysinfo counts start times from boot on some platforms and from the epoch on others
+    let epoch = SystemTime::now()
+      .duration_since(SystemTime::UNIX_EPOCH)?
+      .as_secs();
+    let now = Instant::now();
+    let elapsed = self
+      .last_refresh
+      .replace(now)
+      .map(|last| now.duration_since(last).as_secs_f64());
+
+    let mut processes: Vec<_> = self
+      .system
+      .processes()
+      .iter()
+      .map(|(pid, process)| {
+        let disk_usage = process.disk_usage();
+        let (disk_read, disk_write) = match elapsed {
+          Some(elapsed) if elapsed > 0.0 && self.known.contains(pid) => (
+            (disk_usage.read_bytes as f64 / elapsed) as u64,
+            (disk_usage.written_bytes as f64 / elapsed) as u64,
+          ),
+          _ => (0, 0),
+        };
+        (process, disk_read, disk_write)
+      })
+      .collect();
+
+    match settings.sort_by {
+      ProcessSort::Cpu => processes.sort_by(|(a, ..), (b, ..)| {
+        b.cpu_usage()
+          .partial_cmp(&a.cpu_usage())
+          .unwrap_or(Ordering::Equal)
+      }),
+      ProcessSort::Memory => processes.sort_by_key(|(process, ..)| Reverse(process.memory())),
+      ProcessSort::DiskIo => processes.sort_by_key(|(_, read, write)| Reverse(read + write)),
+    }
+
+    let top = processes
+      .into_iter()
+      .take(settings.count)
+      .map(|(process, disk_read, disk_write)| ProcessStats {
+        pid: process.pid().as_u32(),
+        name: process.name().to_string(),
+        user: self.user_name(process),
+        command: command_line(process, settings),
+        state: process.status().to_string(),
+        start_time: epoch.saturating_sub(process.run_time()),
+        cpu_usage: process.cpu_usage(),
+        memory: process.memory(),
+        disk_read,
+        disk_write,
+      })
+      .collect();
+
+    self.known = self.system.processes().keys().copied().collect();
+    Ok(top)
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "macos"))]
+  fn user_name(&self, process: &Process) -> Option<String> {
+    use sysinfo::UserExt;
+
+    self
+      .system
+      .users()
+      .iter()
+      .find(|user| *user.uid() == process.uid)
+      .map(|user| user.name().to_string())
+  }
+
+  #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+  fn user_name(&self, _process: &Process) -> Option<String> {
+    None
+  }
+}
+
+/// The process' command line with secrets redacted, redacting first so
+/// truncating can't leave part of a secret behind
+fn command_line(process: &Process, settings: &CompiledTopProcesses) -> Option<String> {
+  if !settings.command_line || process.cmd().is_empty() {
+    return None;
+  }
+
+  let mut command = process.cmd().join(" ");
+  for regex in &settings.redact {
+    command = regex.replace_all(&command, "***").into_owned();
+  }
+
+  if settings.max_command_line > 0 {
+    if let Some((index, _)) = command.char_indices().nth(settings.max_command_line) {
+      command.truncate(index);
+    }
+  }
+  Some(command)
+}
+
+impl Collector for TopProcessesCollector {
+  fn name(&self) -> &str {
+    "top_processes"
+  }
+
+  fn enabled(&self) -> bool {
+    false
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let processes = self.get_top_processes(&context.top_processes, context.refresh_lists)?;
+    Ok(serde_json::to_value(processes)?)
+  }
+}
@@ -0,0 +1,778 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::config_manager::RootsConfig;
+use crate::filter::compile_glob;
+use crate::types::{LoadAverage, Pressure, PressureLine};
+
+/// Reads a file under procfs
+pub fn read(roots: &RootsConfig, file: &str) -> Result<String> {
+  let path = roots.proc(file);
+  fs::read_to_string(&path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))
+}
+
+/// Reads a file of `key value` lines like /proc/meminfo or /proc/vmstat,
+/// units like the kB of /proc/meminfo are left out
+pub fn read_values(roots: &RootsConfig, file: &str) -> Result<HashMap<String, u64>> {
+  Ok(parse_values(&read(roots, file)?))
+}
+
+fn parse_values(contents: &str) -> HashMap<String, u64> {
+  contents
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace();
+      let key = fields.next()?.trim_end_matches(':');
+      let value = fields.next()?.parse().ok()?;
+      Some((key.to_string(), value))
+    })
+    .collect()
+}
+
+/// Keeps the counters of the last run to turn the ones of the current run into rates, timed
+/// with Instant so a change of the clock can't skew them. The first run has nothing to compare
+/// with, so it's compared with itself and all its deltas and rates are 0, the same as for a
+/// device, group or unit that first shows up on a later run.
+#[derive(Debug)]
+pub struct CounterHistory<T> {
+  /// The counters of the last run and when they were read
+  last: Option<(T, Instant)>,
+  /// When the run in progress started reading its counters
+  started: Option<Instant>,
+}
+
+impl<T> Default for CounterHistory<T> {
+  fn default() -> Self {
+    Self {
+      last: None,
+      started: None,
+    }
+  }
+}
+
+impl<T> CounterHistory<T> {
+  /// Starts a run, returning the counters of the last one and the seconds since
+  pub fn start(&mut self) -> CounterDelta<T> {
+    let now = Instant::now();
+    self.started = Some(now);
+    match self.last.take() {
+      Some((last, time)) => CounterDelta {
+        last: Some(last),
+        elapsed: now.duration_since(time).as_secs_f64(),
+      },
+      None => CounterDelta {
+        last: None,
+        elapsed: 0.0,
+      },
+    }
+  }
+
+  /// Keeps the counters of the run in progress for the next one to compare with
+  pub fn finish(&mut self, counters: T) {
+    let started = self.started.take().unwrap_or_else(Instant::now);
+    self.last = Some((counters, started));
+  }
+}
+
+/// The counters of the last run and the seconds that passed since, see CounterHistory
+#[derive(Debug)]
+pub struct CounterDelta<T> {
+  last: Option<T>,
+  pub elapsed: f64,
+}
+
+impl<T> CounterDelta<T> {
+  /// The counters of the last run, the current ones on the first run
+  pub fn last<'a>(&'a self, current: &'a T) -> &'a T {
+    self.last.as_ref().unwrap_or(current)
+  }
+
+  /// How much a counter went up per second
+  pub fn rate(&self, now: u64, last: u64) -> f64 {
+    self.per_second(now.saturating_sub(last) as f64)
+  }
+
+  /// An amount per second, 0 when no time passed like on the first run
+  pub fn per_second(&self, amount: f64) -> f64 {
+    if self.elapsed > 0.0 {
+      amount / self.elapsed
+    } else {
+      0.0
+    }
+  }
+}
+
+impl<V> CounterDelta<HashMap<String, V>> {
+  /// The counters of a key on the last run, the current ones when it's new
+  pub fn last_of<'a>(&'a self, key: &str, current: &'a V) -> &'a V {
+    self
+      .last
+      .as_ref()
+      .and_then(|last| last.get(key))
+      .unwrap_or(current)
+  }
+}
+
+/// Keeps the last read of /proc/vmstat to turn its counters into rates
+#[derive(Debug, Default)]
+pub struct VmStat {
+  history: CounterHistory<HashMap<String, u64>>,
+}
+
+/// The /proc/vmstat counters of a read and the ones of the read before
+#[derive(Debug)]
+pub struct VmStatRead {
+  counters: HashMap<String, u64>,
+  delta: CounterDelta<HashMap<String, u64>>,
+}
+
+impl VmStat {
+  pub fn read(&mut self, roots: &RootsConfig) -> Result<VmStatRead> {
+    let counters = read_values(roots, "vmstat")?;
+    let delta = self.history.start();
+    self.history.finish(counters.clone());
+    Ok(VmStatRead { counters, delta })
+  }
+}
+
+impl VmStatRead {
+  /// The counter's value since boot, missing if this kernel doesn't have it
+  pub fn total(&self, counter: &str) -> Option<u64> {
+    self.counters.get(counter).copied()
+  }
+
+  /// How much the counter went up per second since the last read
+  pub fn rate(&self, counter: &str) -> Option<f64> {
+    let now = self.total(counter)?;
+    Some(self.delta.rate(now, *self.delta.last_of(counter, &now)))
+  }
+}
+
+/// The counters of a block device in /proc/diskstats, all of them since boot
+#[derive(Debug, Clone, Default)]
+pub struct DiskCounters {
+  pub reads: u64,
+  pub sectors_read: u64,
+  pub read_ms: u64,
+  pub writes: u64,
+  pub sectors_written: u64,
+  pub write_ms: u64,
+  /// I/Os in progress right now rather than a counter
+  pub in_flight: u64,
+  pub io_ms: u64,
+  pub weighted_io_ms: u64,
+}
+
+/// A block device in /proc/diskstats
+#[derive(Debug, Clone)]
+pub struct DiskStat {
+  pub name: String,
+  /// The major and minor number
+  pub device: (u32, u32),
+  pub counters: DiskCounters,
+}
+
+/// Reads every block device in /proc/diskstats
+pub fn read_diskstats(roots: &RootsConfig) -> Result<Vec<DiskStat>> {
+  Ok(
+    read(roots, "diskstats")?
+      .lines()
+      .filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| fields.get(index).and_then(|value| value.parse().ok());
+        let counters = DiskCounters {
+          reads: number(3)?,
+          sectors_read: number(5)?,
+          read_ms: number(6)?,
+          writes: number(7)?,
+          sectors_written: number(9)?,
+          write_ms: number(10)?,
+          in_flight: number(11)?,
+          io_ms: number(12)?,
+          weighted_io_ms: number(13)?,
+        };
+        Some(DiskStat {
+          name: fields[2].to_string(),
+          device: (fields[0].parse().ok()?, fields[1].parse().ok()?),
+          counters,
+        })
+      })
+      .collect(),
+  )
+}
+
+/// Undoes the octal escapes of spaces and the like in /proc/self/mountinfo
+fn unescape(path: &str) -> String {
+  let mut unescaped = Vec::with_capacity(path.len());
+  let bytes = path.as_bytes();
+  let mut index = 0;
+  while index < bytes.len() {
+    let octal = bytes.get(index + 1..index + 4).and_then(|digits| {
+      let digits = std::str::from_utf8(digits).ok()?;
+      u8::from_str_radix(digits, 8).ok()
+    });
+    match (bytes[index], octal) {
+      (b'\\', Some(byte)) => {
+        unescaped.push(byte);
+        index += 4;
+      }
+      (byte, _) => {
+        unescaped.push(byte);
+        index += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&unescaped).to_string()
+}
+
+/// A mount in /proc/self/mountinfo
+#[derive(Debug, Clone)]
+pub struct Mount {
+  /// The major and minor number, file systems without a block device like NFS have a major of 0
+  pub device: (u32, u32),
+  pub mount_point: String,
+  /// The options of the mount followed by the ones of the file system
+  pub options: Vec<String>,
+  pub fs_type: String,
+  /// What was mounted, like /dev/sda1 or server:/export
+  pub source: String,
+}
+
+/// Reads every mount in /proc/self/mountinfo, see RootsConfig::process
+pub fn read_mounts(roots: &RootsConfig) -> Result<Vec<Mount>> {
+  Ok(
+    read(roots, &roots.process("mountinfo"))?
+      .lines()
+      .filter_map(|line| {
+        // The optional fields before the separator vary in number
+        let (mount, filesystem) = line.split_once(" - ")?;
+        let mount: Vec<&str> = mount.split_whitespace().collect();
+        let mut filesystem = filesystem.split_whitespace();
+        let (major, minor) = mount.get(2)?.split_once(':')?;
+        let fs_type = filesystem.next()?.to_string();
+        let source = unescape(filesystem.next()?);
+
+        let mut options: Vec<String> = mount.get(5)?.split(',').map(String::from).collect();
+        for option in filesystem.next().unwrap_or("").split(',') {
+          if !option.is_empty() && !options.iter().any(|known| known == option) {
+            options.push(option.to_string());
+          }
+        }
+
+        Some(Mount {
+          device: (major.parse().ok()?, minor.parse().ok()?),
+          mount_point: unescape(mount.get(4)?),
+          options,
+          fs_type,
+          source,
+        })
+      })
+      .collect(),
+  )
+}
+
+/// Gets the mount points of every mounted device by its major and minor number
+pub fn mounts_by_device(roots: &RootsConfig) -> Result<HashMap<(u32, u32), Vec<String>>> {
+  let mut mounts: HashMap<_, Vec<String>> = HashMap::new();
+  for mount in read_mounts(roots)? {
+    mounts
+      .entry(mount.device)
+      .or_default()
+      .push(mount.mount_point);
+  }
+  Ok(mounts)
+}
+
+/// The kernel's name for a block device like sda1 or dm-0, None if there's no such device
+pub fn block_device_name(roots: &RootsConfig, device: (u32, u32)) -> Option<String> {
+  let path = roots.sys(&format!("dev/block/{}:{}", device.0, device.1));
+  let path = fs::canonicalize(path).ok()?;
+  Some(path.file_name()?.to_string_lossy().to_string())
+}
+
+/// Whether a block device spins, read from the disk a partition is on.
+/// Named after sysinfo's disk types so disks look the same on every platform.
+pub fn block_device_type(roots: &RootsConfig, name: &str) -> String {
+  let disk = partition_parent(roots, name).unwrap_or_else(|| name.to_string());
+  let rotational = fs::read_to_string(roots.sys(&format!("class/block/{}/queue/rotational", disk)))
+    .ok()
+    .and_then(|value| value.trim().parse::<i32>().ok());
+  match rotational {
+    Some(1) => "HDD".to_string(),
+    Some(0) => "SSD".to_string(),
+    Some(value) => format!("Unknown({})", value),
+    None => "Unknown(-1)".to_string(),
+  }
+}
+
+/// The disk a partition belongs to, None if the block device isn't a partition
+pub fn partition_parent(roots: &RootsConfig, name: &str) -> Option<String> {
+  let path = roots.sys("class/block").join(name);
+  if !path.join("partition").exists() {
+    return None;
+  }
+  let device = fs::canonicalize(path).ok()?;
+  Some(device.parent()?.file_name()?.to_string_lossy().to_string())
+}
+
+/// Where the cgroup v2 hierarchy is mounted, on hybrid setups it's next to the v1 controllers
+pub fn cgroup2_root(roots: &RootsConfig) -> Option<PathBuf> {
+  ["fs/cgroup", "fs/cgroup/unified"]
+    .iter()
+    .map(|path| roots.sys(path))
+    .find(|root| root.join("cgroup.controllers").exists())
+}
+
+/// The runtime and id of the container a group belongs to, from the
+/// names the runtimes give their groups like `docker-<id>.scope`
+pub fn container_id(path: &str) -> Option<(&'static str, &str)> {
+  let mut segments = path.rsplit('/');
+  let name = segments.next()?;
+  let parent = segments.next().unwrap_or("");
+  let name = name.strip_suffix(".scope").unwrap_or(name);
+
+  let (runtime, id) = match name.rsplit_once('-') {
+    Some(("docker", id)) => ("docker", id),
+    Some(("libpod", id)) => ("podman", id),
+    Some(("cri-containerd", id)) => ("containerd", id),
+    Some(("crio", id)) => ("cri-o", id),
+    // Docker's cgroupfs driver names the groups after the bare id
+    None if parent == "docker" => ("docker", name),
+    _ => return None,
+  };
+  let is_id = id.len() == 64 && id.bytes().all(|byte| byte.is_ascii_hexdigit());
+  if is_id {
+    Some((runtime, id))
+  } else {
+    None
+  }
+}
+
+/// Finds the cgroups under the root whose path matches the pattern, by their path.
+/// Every part of the pattern is a glob matched against the directories at that depth,
+/// see validate_cgroup_path.
+pub fn find_cgroups(root: &Path, pattern: &str) -> Vec<(String, PathBuf)> {
+  let mut found = vec![(String::new(), root.to_path_buf())];
+
+  for part in pattern.split('/').filter(|part| !part.is_empty()) {
+    let regex = match compile_glob(part) {
+      Ok(regex) => regex,
+      Err(_) => return vec![],
+    };
+
+    found = found
+      .into_iter()
+      .flat_map(|(name, path)| {
+        let entries = fs::read_dir(&path).into_iter().flatten().flatten();
+        entries
+          .filter(|entry| entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false))
+          .filter_map(|entry| {
+            let child = entry.file_name().to_string_lossy().to_string();
+            if !regex.is_match(&child) {
+              return None;
+            }
+            let name = if name.is_empty() {
+              child
+            } else {
+              format!("{}/{}", name, child)
+            };
+            Some((name, entry.path()))
+          })
+          .collect::<Vec<_>>()
+      })
+      .collect();
+  }
+
+  found
+    .into_iter()
+    .map(|(name, path)| {
+      (
+        if name.is_empty() {
+          "/".to_string()
+        } else {
+          name
+        },
+        path,
+      )
+    })
+    .collect()
+}
+
+/// Reads a cgroup file of `key value` lines like cpu.stat,
+/// None when the cgroup doesn't have the controller
+pub fn read_cgroup_values(path: &Path) -> Option<HashMap<String, u64>> {
+  Some(parse_values(&fs::read_to_string(path).ok()?))
+}
+
+/// Reads a cgroup file of a single number like memory.current,
+/// None when it's missing or `max` for no limit
+pub fn read_cgroup_value(path: &Path) -> Option<u64> {
+  fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// The cgroup v2 group the reporter runs in. In a container with a cgroup
+/// namespace its own group is at the root of the mount.
+pub fn own_cgroup(roots: &RootsConfig) -> Option<PathBuf> {
+  let cgroup = read(roots, &roots.process("cgroup")).ok()?;
+  let path = cgroup.lines().find_map(|line| line.strip_prefix("0::"))?;
+  Some(cgroup2_root(roots)?.join(path.trim().trim_start_matches('/')))
+}
+
+/// How many cores the group's cpu.max lets it use, None for `max`
+pub fn read_cgroup_cpu_limit(group: &Path) -> Option<f32> {
+  let contents = fs::read_to_string(group.join("cpu.max")).ok()?;
+  let mut fields = contents.split_whitespace();
+  let quota: f32 = fields.next()?.parse().ok()?;
+  let period: f32 = fields.next()?.parse().ok()?;
+  if period > 0.0 {
+    Some(quota / period)
+  } else {
+    None
+  }
+}
+
+/// How many cores the group's cpuset lets it run on, from lists like `0-3,6`
+pub fn read_cgroup_cpus(group: &Path) -> Option<usize> {
+  let contents = fs::read_to_string(group.join("cpuset.cpus.effective")).ok()?;
+  let mut cpus = 0;
+  for range in contents.trim().split(',').filter(|range| !range.is_empty()) {
+    cpus += match range.split_once('-') {
+      Some((first, last)) => (last.parse::<usize>().ok()? + 1).checked_sub(first.parse().ok()?)?,
+      None => 1,
+    };
+  }
+  if cpus > 0 {
+    Some(cpus)
+  } else {
+    None
+  }
+}
+
+/// Reads a pressure file like /proc/pressure/cpu or a cgroup's cpu.pressure
+pub fn read_pressure(path: &Path) -> Option<Pressure> {
+  let contents = fs::read_to_string(path).ok()?;
+  let mut some = None;
+  let mut full = None;
+
+  for line in contents.lines() {
+    let mut fields = line.split_whitespace();
+    let kind = fields.next();
+    let mut pressure = PressureLine::default();
+    for field in fields {
+      match field.split_once('=') {
+        Some(("avg10", value)) => pressure.avg10 = value.parse().unwrap_or(0.0),
+        Some(("avg60", value)) => pressure.avg60 = value.parse().unwrap_or(0.0),
+        Some(("avg300", value)) => pressure.avg300 = value.parse().unwrap_or(0.0),
+        Some(("total", value)) => pressure.total = value.parse().unwrap_or(0),
+        _ => {}
+      }
+    }
+
+    match kind {
+      Some("some") => some = Some(pressure),
+      Some("full") => full = Some(pressure),
+      _ => {}
+    }
+  }
+
+  Some(Pressure { some: some?, full })
+}
+
+/// Reads /proc/loadavg
+pub fn read_load_average(roots: &RootsConfig) -> Result<LoadAverage> {
+  let contents = read(roots, "loadavg")?;
+  let mut fields = contents.split_whitespace().map(str::parse::<f64>);
+  let mut next = || {
+    fields
+      .next()
+      .and_then(|field| field.ok())
+      .ok_or_else(|| anyhow!("loadavg is malformed: {}", contents.trim()))
+  };
+  Ok(LoadAverage {
+    one: next()?,
+    five: next()?,
+    fifteen: next()?,
+  })
+}
+
+/// The bytes received and sent by every network interface in net/dev, see RootsConfig::process
+pub fn read_net_dev(roots: &RootsConfig) -> Result<HashMap<String, (u64, u64)>> {
+  Ok(
+    read(roots, &roots.process("net/dev"))?
+      .lines()
+      .filter_map(|line| {
+        // The first two lines are headers without a colon
+        let (name, counters) = line.split_once(':')?;
+        let counters: Vec<u64> = counters
+          .split_whitespace()
+          .map(|value| value.parse().ok())
+          .collect::<Option<_>>()?;
+        Some((
+          name.trim().to_string(),
+          (*counters.first()?, *counters.get(8)?),
+        ))
+      })
+      .collect(),
+  )
+}
+
+/// Time a CPU spent in each state in clock ticks, in the order of /proc/stat
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuTicks {
+  pub user: u64,
+  pub nice: u64,
+  pub system: u64,
+  pub idle: u64,
+  pub iowait: u64,
+  pub irq: u64,
+  pub softirq: u64,
+  pub steal: u64,
+  /// Already counted in user
+  pub guest: u64,
+}
+
+impl CpuTicks {
+  fn parse(fields: &[u64]) -> Self {
+    let field = |index: usize| fields.get(index).copied().unwrap_or(0);
+    Self {
+      user: field(0),
+      nice: field(1),
+      system: field(2),
+      idle: field(3),
+      iowait: field(4),
+      irq: field(5),
+      softirq: field(6),
+      steal: field(7),
+      guest: field(8),
+    }
+  }
+
+  /// All of the ticks, without guest since it's part of user
+  pub fn total(&self) -> u64 {
+    self.user
+      + self.nice
+      + self.system
+      + self.idle
+      + self.iowait
+      + self.irq
+      + self.softirq
+      + self.steal
+  }
+}
+
+/// The counters of /proc/stat, all of them since boot
+#[derive(Debug, Clone, Default)]
+pub struct ProcStat {
+  pub cpu: CpuTicks,
+  pub cores: Vec<CpuTicks>,
+  pub context_switches: u64,
+  pub interrupts: u64,
+  pub forks: u64,
+  pub running: u64,
+  pub blocked: u64,
+}
+
+impl ProcStat {
+  pub fn read(roots: &RootsConfig) -> Result<Self> {
+    Ok(Self::parse(&read(roots, "stat")?))
+  }
+
+  fn parse(contents: &str) -> Self {
+    let mut stat = Self::default();
+    for line in contents.lines() {
+      let mut fields = line.split_whitespace();
+      let key = match fields.next() {
+        Some(key) => key,
+        None => continue,
+      };
+      let values: Vec<u64> = fields.filter_map(|value| value.parse().ok()).collect();
+      let first = values.first().copied().unwrap_or(0);
+
+      match key {
+        "cpu" => stat.cpu = CpuTicks::parse(&values),
+        _ if key.starts_with("cpu") => stat.cores.push(CpuTicks::parse(&values)),
+        "ctxt" => stat.context_switches = first,
+        // The first number is the total, the rest are per interrupt
+        "intr" => stat.interrupts = first,
+        "processes" => stat.forks = first,
+        "procs_running" => stat.running = first,
+        "procs_blocked" => stat.blocked = first,
+        _ => {}
+      }
+    }
+    stat
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{thread, time::Duration};
+
+  /// A path in the fixture tree of a host, laid out like the host's / mounted in a container
+  fn fixture(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("tests/fixtures/host")
+      .join(path)
+  }
+
+  fn roots() -> RootsConfig {
+    RootsConfig {
+      proc: fixture("proc"),
+      sys: fixture("sys"),
+      root: fixture(""),
+    }
+  }
+
+  #[test]
+  fn parses_proc_stat() {
+    let stat = ProcStat::read(&roots()).unwrap();
+    assert_eq!(stat.cpu.user, 4705);
+    assert_eq!(stat.cpu.guest, 100);
+    assert_eq!(stat.cpu.total(), 4705 + 150 + 1120 + 16250 + 520 + 35);
+    assert_eq!(stat.cores.len(), 2);
+    assert_eq!(stat.cores[1].softirq, 15);
+    assert_eq!(stat.interrupts, 114930548);
+    assert_eq!(stat.context_switches, 1990473);
+    assert_eq!(stat.forks, 2915);
+    assert_eq!((stat.running, stat.blocked), (3, 1));
+  }
+
+  #[test]
+  fn reads_pressure_with_and_without_full() {
+    let cpu = read_pressure(&fixture("proc/pressure/cpu")).unwrap();
+    assert_eq!(cpu.some.avg10, 1.53);
+    assert_eq!(cpu.some.avg300, 0.28);
+    assert_eq!(cpu.some.total, 3865417);
+    assert_eq!(cpu.full.unwrap().total, 0);
+
+    // Kernels before 5.13 only have the some line for the CPU
+    let io = read_pressure(&fixture("proc/pressure/io")).unwrap();
+    assert_eq!(io.some.avg60, 0.12);
+    assert!(io.full.is_none());
+
+    assert!(read_pressure(&fixture("proc/pressure/memory")).is_none());
+  }
+
+  #[test]
+  fn reads_diskstats_of_every_kernel_version() {
+    let disks = read_diskstats(&roots()).unwrap();
+    // Lines that don't parse, like the one of dm-0, are left out
+    assert_eq!(disks.len(), 2);
+    let sda = &disks[0];
+    assert_eq!((sda.name.as_str(), sda.device), ("sda", (8, 0)));
+    assert_eq!(sda.counters.reads, 41532);
+    assert_eq!(sda.counters.sectors_written, 4413978);
+    assert_eq!(sda.counters.in_flight, 2);
+    assert_eq!(sda.counters.weighted_io_ms, 152224);
+    assert_eq!(disks[1].counters.weighted_io_ms, 138684);
+  }
+
+  #[test]
+  fn reads_the_mounts_of_the_hosts_init() {
+    let mounts = read_mounts(&roots()).unwrap();
+    assert_eq!(mounts.len(), 4);
+
+    let backup = &mounts[2];
+    assert_eq!(backup.mount_point, "/mnt/backup disk");
+    assert_eq!(backup.device, (8, 17));
+    assert_eq!(
+      (backup.fs_type.as_str(), backup.source.as_str()),
+      ("xfs", "/dev/sdb1")
+    );
+    // The file system's rw isn't repeated after the mount's
+    assert_eq!(backup.options, ["rw", "noatime", "attr2"]);
+
+    let share = &mounts[3];
+    assert_eq!(share.device.0, 0);
+    assert_eq!(share.source, "nas:/export/share");
+  }
+
+  #[test]
+  fn unescapes_octal_escapes_only() {
+    assert_eq!(unescape("/mnt/a\\040b\\011c"), "/mnt/a b\tc");
+    assert_eq!(unescape("/mnt/back\\134slash"), "/mnt/back\\slash");
+    assert_eq!(unescape("/mnt/not\\8escaped\\"), "/mnt/not\\8escaped\\");
+  }
+
+  #[test]
+  fn counts_the_cpus_of_a_cpuset() {
+    let cgroup = fixture("sys/fs/cgroup");
+    assert_eq!(read_cgroup_cpus(&cgroup), Some(8));
+    assert_eq!(read_cgroup_cpus(&cgroup.join("system.slice")), Some(5));
+    assert_eq!(read_cgroup_cpus(&cgroup.join("user.slice")), None);
+    assert_eq!(read_cgroup_cpus(&cgroup.join("init.scope")), None);
+  }
+
+  #[test]
+  fn finds_cgroups_by_glob() {
+    let root = fixture("sys/fs/cgroup");
+    let names = |pattern: &str| {
+      let mut names: Vec<String> = find_cgroups(&root, pattern)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+      names.sort();
+      names
+    };
+
+    assert_eq!(names("/"), ["/"]);
+    assert_eq!(names("*.slice"), ["system.slice", "user.slice"]);
+    assert_eq!(
+      names("*.slice/docker-*.scope"),
+      [format!(
+        "system.slice/docker-{}.scope",
+        "3f4a9c1e5b7d2a8f6e0c4b9a1d3e5f7a9b2c4d6e8f0a1b3c5d7e9f1a2b3c4d5e"
+      )]
+    );
+    assert_eq!(
+      names("system.slice/*.service"),
+      ["system.slice/nginx.service"]
+    );
+    assert!(names("docker/*").is_empty());
+  }
+
+  #[test]
+  fn reads_the_hosts_load_average_and_interfaces() {
+    let load = read_load_average(&roots()).unwrap();
+    assert_eq!((load.one, load.five, load.fifteen), (0.52, 0.58, 0.61));
+
+    let interfaces = read_net_dev(&roots()).unwrap();
+    assert_eq!(interfaces.len(), 2);
+    assert_eq!(interfaces["eth0"], (1936240934, 128532110));
+    assert_eq!(interfaces["lo"], (5412, 5412));
+  }
+
+  #[test]
+  fn finds_the_cgroup_of_the_hosts_init() {
+    assert_eq!(
+      own_cgroup(&roots()),
+      Some(fixture("sys/fs/cgroup/init.scope"))
+    );
+  }
+
+  #[test]
+  fn rates_are_zero_until_there_is_a_run_to_compare_with() {
+    let mut history = CounterHistory::default();
+    let mut current = HashMap::new();
+    current.insert("sda".to_string(), 100);
+
+    let delta = history.start();
+    assert_eq!(delta.last_of("sda", &100), &100);
+    assert_eq!(delta.rate(100, *delta.last_of("sda", &100)), 0.0);
+    history.finish(current);
+
+    thread::sleep(Duration::from_millis(20));
+    let delta = history.start();
+    assert!(delta.elapsed > 0.0);
+    assert_eq!(delta.last_of("sda", &300), &100);
+    assert_eq!(delta.last_of("sdb", &50), &50);
+    let rate = delta.rate(300, *delta.last_of("sda", &300));
+    assert!((rate - 200.0 / delta.elapsed).abs() < 1e-6);
+    // Counters that went backwards, like after a device was replaced, aren't negative
+    assert_eq!(delta.rate(10, 100), 0.0);
+  }
+}
@@ -1,26 +1,141 @@
 use anyhow::Result;
-use sysinfo::SystemExt;
+use serde_json::Value;
+use sysinfo::{System, SystemExt};
 
+use crate::config_manager::RootsConfig;
 use crate::types::{RAMStats, SwapStats};
 
-use super::DataCollector;
+use super::collector::{CollectContext, Collector};
+use super::environment;
 
-impl DataCollector {
-  /// Gets the current RAM stats
-  pub fn get_ram(&mut self) -> Result<RAMStats> {
-    self.fetcher.refresh_memory();
+#[cfg(target_os = "linux")]
+use crate::types::MemoryDetails;
+
+#[cfg(target_os = "linux")]
+use super::procfs::{self, VmStat};
+
+#[derive(Debug, Default)]
+pub struct RamCollector {
+  system: System,
+  #[cfg(target_os = "linux")]
+  vmstat: VmStat,
+}
+
+/// Swap has a collector of its own so it can be turned off and scheduled separately
+#[derive(Debug, Default)]
+pub struct SwapCollector {
+  system: System,
+  #[cfg(target_os = "linux")]
+  vmstat: VmStat,
+}
+
+impl RamCollector {
+  /// Gets the current RAM stats, the container's usage and limit when scoped to it
+  pub fn get_ram(&mut self, scope_to_cgroup: bool, roots: &RootsConfig) -> Result<RAMStats> {
+    self.system.refresh_memory();
+    let (mut used, mut total) = (self.system.used_memory(), self.system.total_memory());
+    if scope_to_cgroup {
+      if let Some((container_used, limit)) = environment::container_memory(roots) {
+        used = container_used / 1000;
+        total = limit.map_or(total, |limit| total.min(limit / 1000));
+      }
+    }
+
+    // The breakdown is extra, the usage is still worth sending without it
+    #[cfg(target_os = "linux")]
+    let details = match self.get_details(roots) {
+      Ok(details) => Some(details),
+      Err(e) => {
+        debug!("No memory breakdown: {}", e);
+        None
+      }
+    };
+    #[cfg(not(target_os = "linux"))]
+    let details = None;
 
     Ok(RAMStats {
-      used: self.fetcher.used_memory(),
-      total: self.fetcher.total_memory(),
+      used,
+      total,
+      details,
+    })
+  }
+
+  /// Gets the breakdown of /proc/meminfo and the page fault rates since the last run
+  #[cfg(target_os = "linux")]
+  fn get_details(&mut self, roots: &RootsConfig) -> Result<MemoryDetails> {
+    let meminfo = procfs::read_values(roots, "meminfo")?;
+    let vmstat = self.vmstat.read(roots)?;
+    let count = |key: &str| meminfo.get(key).copied().unwrap_or(0);
+    // /proc/meminfo is in KiB while sysinfo reports KB
+    let value = |key: &str| count(key) * 1024 / 1000;
+
+    Ok(MemoryDetails {
+      available: value("MemAvailable"),
+      free: value("MemFree"),
+      buffers: value("Buffers"),
+      cached: value("Cached"),
+      shared: value("Shmem"),
+      slab_reclaimable: value("SReclaimable"),
+      slab_unreclaimable: value("SUnreclaim"),
+      dirty: value("Dirty"),
+      writeback: value("Writeback"),
+      huge_pages_total: count("HugePages_Total"),
+      huge_pages_free: count("HugePages_Free"),
+      huge_page_size: value("Hugepagesize"),
+      committed: value("Committed_AS"),
+      commit_limit: value("CommitLimit"),
+      page_faults: vmstat.rate("pgfault"),
+      major_page_faults: vmstat.rate("pgmajfault"),
+      oom_kills: vmstat.total("oom_kill"),
     })
   }
+}
 
+impl SwapCollector {
   /// Gets the current swap states
-  pub fn get_swap(&mut self) -> Result<SwapStats> {
+  pub fn get_swap(&mut self, roots: &RootsConfig) -> Result<SwapStats> {
+    self.system.refresh_memory();
+
+    #[cfg(target_os = "linux")]
+    let (swap_in, swap_out) = match self.vmstat.read(roots) {
+      Ok(vmstat) => (vmstat.rate("pswpin"), vmstat.rate("pswpout")),
+      Err(e) => {
+        debug!("No swap activity: {}", e);
+        (None, None)
+      }
+    };
+    #[cfg(not(target_os = "linux"))]
+    let (swap_in, swap_out) = (None, None);
+
     Ok(SwapStats {
-      used: self.fetcher.used_swap(),
-      total: self.fetcher.total_swap(),
+      used: self.system.used_swap(),
+      total: self.system.total_swap(),
+      swap_in,
+      swap_out,
     })
   }
 }
+
+impl Collector for RamCollector {
+  fn name(&self) -> &str {
+    "ram"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let collection = &context.collection;
+    let ram = self.get_ram(collection.container.scope_to_cgroup, &collection.roots)?;
+    Ok(serde_json::to_value(ram)?)
+  }
+}
+
+impl Collector for SwapCollector {
+  fn name(&self) -> &str {
+    "swap"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    Ok(serde_json::to_value(
+      self.get_swap(&context.collection.roots)?,
+    )?)
+  }
+}
@@ -0,0 +1,276 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::config_manager::RootsConfig;
+use crate::types::SystemdStats;
+
+use super::collector::{CollectContext, Collector};
+use super::DataCollectorError;
+
+#[cfg(target_os = "linux")]
+use super::exec;
+
+#[cfg(target_os = "linux")]
+use crate::types::SystemdUnitStats;
+
+#[cfg(target_os = "linux")]
+use std::{
+  collections::{HashMap, HashSet},
+  mem,
+  path::Path,
+  time::Instant,
+};
+
+#[cfg(target_os = "linux")]
+use super::procfs::CounterHistory;
+
+/// What `systemctl show` is asked for about every unit
+#[cfg(target_os = "linux")]
+const PROPERTIES: &str = "Id,LoadState,ActiveState,SubState,Result,NRestarts,MemoryCurrent,\
+  CPUUsageNSec,StateChangeTimestampMonotonic";
+
+#[derive(Debug, Default)]
+pub struct SystemdCollector {
+  /// The CPU time of every unit in nanoseconds
+  #[cfg(target_os = "linux")]
+  history: CounterHistory<HashMap<String, u64>>,
+}
+
+/// Splits the output of `systemctl show` into the properties of
+/// every unit, which it separates with blank lines
+#[cfg(target_os = "linux")]
+fn parse_show(output: &str) -> Vec<HashMap<&str, &str>> {
+  output
+    .split("\n\n")
+    .map(|unit| {
+      unit
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(_, value)| !value.is_empty())
+        .collect::<HashMap<_, _>>()
+    })
+    .filter(|properties| !properties.is_empty())
+    .collect()
+}
+
+/// Runs `systemctl show` for the units. exec::run kills it a little before the timeout.
+#[cfg(target_os = "linux")]
+fn show(units: &[String], timeout: Duration) -> Result<String> {
+  let mut args = vec![
+    "show".to_string(),
+    format!("--property={}", PROPERTIES),
+    "--".to_string(),
+  ];
+  args.extend(units.iter().cloned());
+  let (status, stdout, stderr) = exec::run("systemctl", &args, timeout)?;
+  if !status.success() {
+    return Err(exec::failed(status, &stderr).into());
+  }
+  Ok(stdout)
+}
+
+/// Runs `systemctl show` for one unit at a time with what's left until the deadline,
+/// leaving out the units it fails for
+#[cfg(target_os = "linux")]
+fn show_each(units: &[String], deadline: Instant) -> String {
+  let mut output = String::new();
+  for (index, unit) in units.iter().enumerate() {
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+      debug!(
+        "No time left to get the state of {} more units",
+        units.len() - index
+      );
+      break;
+    }
+    match show(std::slice::from_ref(unit), left) {
+      Ok(unit_output) => {
+        output.push_str(&unit_output);
+        // parse_show splits the units on blank lines
+        output.push('\n');
+      }
+      Err(e) => debug!("Could not get the state of {}: {}", unit, e),
+    }
+  }
+  output
+}
+
+/// Whether the error is the one of a command that ran and failed rather than timed out
+#[cfg(target_os = "linux")]
+fn is_exec_failure(error: &anyhow::Error) -> bool {
+  matches!(
+    error.downcast_ref::<DataCollectorError>(),
+    Some(DataCollectorError::ExecFailed { .. })
+  )
+}
+
+/// Parses one of systemd's counters, None when it isn't tracked, which
+/// newer versions show as `[not set]` and older ones as the largest u64
+#[cfg(target_os = "linux")]
+fn counter(value: Option<&&str>) -> Option<u64> {
+  value?.parse().ok().filter(|value| *value != u64::MAX)
+}
+
+/// CLOCK_MONOTONIC in microseconds, the clock of the `*TimestampMonotonic` properties
+#[cfg(target_os = "linux")]
+fn monotonic_now() -> u64 {
+  // SAFETY: clock_gettime only writes to the struct, which is plain data
+  let mut time: libc::timespec = unsafe { mem::zeroed() };
+  unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+  time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1_000
+}
+
+impl SystemdCollector {
+  /// Gets the state of the units from `systemctl show`, which expands the globs
+  #[cfg(target_os = "linux")]
+  pub fn get_units(
+    &mut self,
+    units: &[String],
+    roots: &RootsConfig,
+    timeout: Duration,
+  ) -> Result<SystemdStats> {
+    // systemctl talks to the systemd the reporter runs under, never to the one of a host whose
+    // procfs it reads. The check for one is the same as sd_booted's.
+    if !roots.is_own_proc() || !Path::new("/run/systemd/system").exists() {
+      return Err(DataCollectorError::NoSystemd.into());
+    }
+
+    let mut stats = SystemdStats {
+      units: Vec::new(),
+      failed: Vec::new(),
+    };
+    // Without any units systemctl shows the properties of systemd itself
+    if units.is_empty() {
+      return Ok(stats);
+    }
+
+    let deadline = Instant::now() + timeout;
+    let stdout = match show(units, timeout) {
+      Ok(stdout) => stdout,
+      // A single name systemctl doesn't take, like a malformed one, fails the whole call
+      Err(e) if units.len() > 1 && is_exec_failure(&e) => {
+        debug!(
+          "systemctl show failed, getting the units one at a time: {}",
+          e
+        );
+        let stdout = show_each(units, deadline);
+        if stdout.trim().is_empty() {
+          return Err(e);
+        }
+        stdout
+      }
+      Err(e) => return Err(e),
+    };
+
+    let delta = self.history.start();
+    let monotonic = monotonic_now();
+
+    // A unit can match more than one glob but is only reported once
+    let mut seen = HashSet::new();
+    let mut cpu_times = HashMap::new();
+    for properties in parse_show(&stdout) {
+      let name = match properties.get("Id") {
+        Some(name) => name.to_string(),
+        None => continue,
+      };
+      if !seen.insert(name.clone()) {
+        continue;
+      }
+      let property = |key: &str| properties.get(key).map_or("", |value| *value).to_string();
+
+      let cpu_time = counter(properties.get("CPUUsageNSec"));
+      let cpu_usage =
+        cpu_time.map(|time| (delta.rate(time, *delta.last_of(&name, &time)) / 10_000_000.0) as f32);
+      if let Some(cpu_time) = cpu_time {
+        cpu_times.insert(name.clone(), cpu_time);
+      }
+      // 0 when the unit didn't change its state since boot
+      let changed =
+        counter(properties.get("StateChangeTimestampMonotonic")).filter(|time| *time > 0);
+
+      let active_state = property("ActiveState");
+      if active_state == "failed" {
+        stats.failed.push(name.clone());
+      }
+      stats.units.push(SystemdUnitStats {
+        load_state: property("LoadState"),
+        active_state,
+        sub_state: property("SubState"),
+        result: properties.get("Result").map(|result| result.to_string()),
+        restarts: properties
+          .get("NRestarts")
+          .and_then(|restarts| restarts.parse().ok()),
+        // systemd counts bytes while RAMStats and the processes are in KB
+        memory: counter(properties.get("MemoryCurrent")).map(|bytes| bytes / 1000),
+        cpu_usage,
+        cpu_time: cpu_time.map(|time| time as f64 / 1_000_000_000.0),
+        state_age: changed.map(|time| monotonic.saturating_sub(time) as f64 / 1_000_000.0),
+        name,
+      });
+    }
+
+    self.history.finish(cpu_times);
+    Ok(stats)
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  pub fn get_units(
+    &mut self,
+    _units: &[String],
+    _roots: &RootsConfig,
+    _timeout: Duration,
+  ) -> Result<SystemdStats> {
+    Err(DataCollectorError::Unsupported.into())
+  }
+}
+
+impl Collector for SystemdCollector {
+  fn name(&self) -> &str {
+    "systemd"
+  }
+
+  fn enabled(&self) -> bool {
+    false
+  }
+
+  fn interval(&self) -> Option<f64> {
+    Some(10.0)
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    let collection = &context.collection;
+    let units = self.get_units(
+      &collection.systemd.units,
+      &collection.roots,
+      context.timeout,
+    )?;
+    Ok(serde_json::to_value(units)?)
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  #[test]
+  fn parses_the_units_of_systemctl_show() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/systemd/show");
+    let output = fs::read_to_string(path).unwrap();
+    let units = parse_show(&output);
+    assert_eq!(units.len(), 3);
+
+    assert_eq!(units[0]["Id"], "nginx.service");
+    assert_eq!(counter(units[0].get("NRestarts")), Some(2));
+    assert_eq!(counter(units[0].get("CPUUsageNSec")), Some(1520000000));
+
+    // Counters systemd doesn't track and empty properties are None
+    assert_eq!(units[1]["ActiveState"], "failed");
+    assert_eq!(counter(units[1].get("MemoryCurrent")), None);
+    assert_eq!(counter(units[1].get("CPUUsageNSec")), None);
+    assert!(!units[1].contains_key("StateChangeTimestampMonotonic"));
+
+    assert_eq!(units[2]["LoadState"], "not-found");
+  }
+}
@@ -1,22 +1,38 @@
-use crate::{data_collector::DataCollectorError, types::TempStats};
+use crate::{data_collector::DataCollectorError, filter::CompiledFilters, types::TempStats};
 use anyhow::{anyhow, Result};
-use sysinfo::{ComponentExt, SystemExt};
+use serde_json::Value;
+use sysinfo::{ComponentExt, RefreshKind, System, SystemExt};
 
-use super::DataCollector;
+use super::collector::{CollectContext, Collector};
+
+#[derive(Debug)]
+pub struct TempCollector {
+  system: System,
+}
+
+impl TempCollector {
+  pub fn new() -> Self {
+    Self {
+      system: System::new_with_specifics(RefreshKind::new().with_components_list()),
+    }
+  }
 
-impl DataCollector {
   /// Get the current temperature of the system
-  pub fn get_temps(&mut self) -> Result<Vec<TempStats>> {
-    self.fetcher.refresh_components();
+  pub fn get_temps(&mut self, filters: &CompiledFilters) -> Result<Vec<TempStats>> {
+    self.system.refresh_components();
 
-    let components = self.fetcher.components();
+    let components = self.system.components();
 
     if components.is_empty() {
-      return Err(anyhow!(DataCollectorError::NoTemp));
+      return Err(anyhow!(DataCollectorError::NoSensors));
     };
 
     let mut temps = Vec::<TempStats>::new();
     for component in components {
+      if !filters.temps.allows(component.label()) {
+        continue;
+      }
+
       let temp = component.temperature();
       temps.push(TempStats {
         label: component.label().to_string(),
@@ -26,3 +42,13 @@ impl DataCollector {
     Ok(temps)
   }
 }
+
+impl Collector for TempCollector {
+  fn name(&self) -> &str {
+    "temps"
+  }
+
+  fn collect(&mut self, context: &CollectContext) -> Result<Value> {
+    Ok(serde_json::to_value(self.get_temps(&context.filters)?)?)
+  }
+}
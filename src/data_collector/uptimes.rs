@@ -1,27 +1,26 @@
 use std::time::SystemTime;
 
-use anyhow::Result;
 use sysinfo::SystemExt;
 
 use super::DataCollector;
 
+/// Milliseconds since the epoch, 0 for a clock set before it
+fn now_millis() -> u128 {
+  SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis()
+}
+
 impl DataCollector {
-  /// Get uptime of the system
-  pub fn get_uptime(&mut self) -> Result<u64> {
-    let boot_time = self.fetcher.boot_time() * 1000;
-    let timeframe = SystemTime::now()
-      .duration_since(SystemTime::UNIX_EPOCH)?
-      .as_millis() as u64
-      - boot_time;
-    Ok(timeframe)
+  /// Get uptime of the system, a clock that was set back can't fail the sample
+  pub fn get_uptime(&mut self) -> u64 {
+    let boot_time = self.fetcher.boot_time() as u128 * 1000;
+    now_millis().saturating_sub(boot_time) as u64
   }
 
   /// Get uptime of the reporter
-  pub fn get_reporter_uptime(&mut self) -> Result<u64> {
-    let timeframe = SystemTime::now()
-      .duration_since(SystemTime::UNIX_EPOCH)?
-      .as_millis()
-      - self.start_timestamp;
-    Ok(timeframe as u64)
+  pub fn get_reporter_uptime(&mut self) -> u64 {
+    now_millis().saturating_sub(self.start_timestamp) as u64
   }
 }
@@ -0,0 +1,85 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Include and exclude patterns from the config.
+///
+/// Patterns are globs (`*` and `?` wildcards) unless they're wrapped
+/// in slashes like `/^sd[a-z]$/`, in which case they're regular expressions.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+  pub include: Vec<String>,
+  pub exclude: Vec<String>,
+}
+
+impl Filter {
+  pub fn exclude(patterns: &[&str]) -> Self {
+    Self {
+      include: vec![],
+      exclude: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+    }
+  }
+
+  pub fn compile(&self) -> Result<CompiledFilter, regex::Error> {
+    Ok(CompiledFilter {
+      include: compile_patterns(&self.include)?,
+      exclude: compile_patterns(&self.exclude)?,
+    })
+  }
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>, regex::Error> {
+  patterns
+    .iter()
+    .map(|pattern| compile_pattern(pattern))
+    .collect()
+}
+
+/// Turns a glob or a slash delimited regex into a regex
+pub fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+  if pattern.len() >= 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+    return Regex::new(&pattern[1..pattern.len() - 1]);
+  }
+  compile_glob(pattern)
+}
+
+/// Turns a glob with `*` and `?` into a regex, everything else matches itself
+pub fn compile_glob(pattern: &str) -> Result<Regex, regex::Error> {
+  let mut regex = String::from("^");
+  for character in pattern.chars() {
+    match character {
+      '*' => regex.push_str(".*"),
+      '?' => regex.push('.'),
+      _ => regex.push_str(&regex::escape(&character.to_string())),
+    }
+  }
+  regex.push('$');
+  Regex::new(&regex)
+}
+
+/// A filter whose patterns have been compiled and are ready to be matched against
+#[derive(Clone, Debug, Default)]
+pub struct CompiledFilter {
+  include: Vec<Regex>,
+  exclude: Vec<Regex>,
+}
+
+impl CompiledFilter {
+  /// Whether the value passes the filter, an empty include list lets everything in
+  pub fn allows(&self, value: &str) -> bool {
+    let included =
+      self.include.is_empty() || self.include.iter().any(|regex| regex.is_match(value));
+    included && !self.exclude.iter().any(|regex| regex.is_match(value))
+  }
+}
+
+/// The compiled form of the config's collection filters
+#[derive(Clone, Debug, Default)]
+pub struct CompiledFilters {
+  pub disks: CompiledFilter,
+  pub mounts: CompiledFilter,
+  pub filesystems: CompiledFilter,
+  pub nics: CompiledFilter,
+  pub temps: CompiledFilter,
+  pub block_devices: CompiledFilter,
+}
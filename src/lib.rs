@@ -0,0 +1,129 @@
+//! The reporter as a library, so other crates can add collectors of their own:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! # struct Backups;
+//! # impl xornet_reporter::data_collector::Collector for Backups {
+//! #   fn name(&self) -> &str { "backups" }
+//! #   fn collect(
+//! #     &mut self,
+//! #     _: &xornet_reporter::data_collector::CollectContext,
+//! #   ) -> anyhow::Result<serde_json::Value> {
+//! #     Ok(serde_json::Value::Null)
+//! #   }
+//! # }
+//! let mut reporter = xornet_reporter::reporter::Reporter::new().await?;
+//! reporter.data_collector.registry.register(Box::new(Backups))?;
+//! std::process::exit(xornet_reporter::run(reporter).await);
+//! # }
+//! ```
+use std::time::{Duration, Instant};
+
+extern crate nvml_wrapper as nvml;
+
+#[macro_use]
+pub mod logger;
+
+pub mod arg_parser;
+pub mod auth_manager;
+pub mod config_manager;
+pub mod data_collector;
+pub mod filter;
+pub mod reporter;
+pub mod scheduler;
+pub mod service_manager;
+pub mod systemd;
+pub mod telemetry;
+pub mod types;
+pub mod util;
+pub mod websocket_manager;
+use crate::reporter::{Reporter, EXIT_SHUTDOWN, EXIT_SHUTDOWN_FAILED};
+use crate::scheduler::Scheduler;
+use crate::systemd::Watchdog;
+
+/// Collects and sends samples until the reporter is asked to shut down,
+/// returns the code the process should exit with
+pub async fn run(mut reporter: Reporter) -> i32 {
+  let shutdown = reporter.shutdown.clone();
+  let watchdog = Watchdog::from_env();
+  let mut scheduler = Scheduler::new(reporter.interval());
+
+  loop {
+    let start_time = Instant::now();
+
+    let skipped_ticks = scheduler.start_tick();
+    if skipped_ticks > 0 {
+      warn!(
+        skipped_ticks = skipped_ticks;
+        "Skipped {} tick(s) because the last one took longer than the interval",
+        skipped_ticks
+      );
+      reporter.telemetry.skipped_ticks += skipped_ticks;
+    }
+
+    if let Err(e) = reporter.reload_config_if_requested().await {
+      error!("Error while reloading config: {}", e);
+    }
+    scheduler.set_interval(reporter.interval());
+
+    let fetch_start_time = Instant::now();
+    if let Err(e) = reporter.update_dynamic_data().await {
+      error!("{}", e);
+    }
+    let fetch_elapsed = fetch_start_time.elapsed();
+
+    let send_start_time = Instant::now();
+    let send_result = reporter.send_dynamic_data().await;
+    let send_elapsed = send_start_time.elapsed();
+    if let Err(e) = &send_result {
+      error!("Error while sending dynamic data: {}", e);
+    }
+    reporter.notify_status(&send_result, send_elapsed);
+    watchdog.ping();
+
+    if let Err(e) = reporter.send_static_data_if_due().await {
+      error!("Error while sending static data: {}", e);
+    }
+
+    let total_elapsed = start_time.elapsed();
+    let interval = reporter.interval();
+    reporter.telemetry.record_tick(total_elapsed, interval);
+
+    // Waiting until the next boundary of the interval keeps the ticks from drifting
+    let rest_time = scheduler.time_until_next_tick().as_secs_f64();
+
+    debug!(
+      fetch_ms = fetch_elapsed.as_millis(),
+      send_ms = send_elapsed.as_millis(),
+      total_ms = total_elapsed.as_millis(),
+      rest_s = rest_time;
+      "Fetch: [{}ms] Send: [{}ms] Total: [{}ms] - Rest: [{}s]",
+      fetch_elapsed.as_millis(),
+      send_elapsed.as_millis(),
+      total_elapsed.as_millis(),
+      rest_time
+    );
+
+    // Only stop between ticks so the last sample still gets sent
+    if shutdown.is_requested() {
+      break;
+    }
+
+    tokio::select! {
+      _ = watchdog.sleep(Duration::from_secs_f64(rest_time)) => {}
+      _ = shutdown.requested() => break,
+    }
+  }
+
+  if let Err(e) = systemd::notify("STOPPING=1") {
+    error!("Error while notifying systemd: {}", e);
+  }
+
+  match reporter.disconnect().await {
+    Ok(_) => EXIT_SHUTDOWN,
+    Err(e) => {
+      error!("Error while shutting down: {}", e);
+      EXIT_SHUTDOWN_FAILED
+    }
+  }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use crate::systemd;
+
+/// The prefix module paths have, stripped so filters can use `data_collector` etc.
+const CRATE_PREFIX: &str = concat!(env!("CARGO_CRATE_NAME"), "::");
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+  Error,
+  Warn,
+  Info,
+  Debug,
+  Trace,
+}
+
+impl Level {
+  /// The matching syslog priority used by journald
+  fn priority(&self) -> u8 {
+    match self {
+      Level::Error => 3,
+      Level::Warn => 4,
+      Level::Info => 6,
+      Level::Debug | Level::Trace => 7,
+    }
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      Level::Error => "error",
+      Level::Warn => "warn",
+      Level::Info => "info",
+      Level::Debug => "debug",
+      Level::Trace => "trace",
+    }
+  }
+
+  /// Moves the level by the number of -v (positive) or -q (negative) flags
+  pub fn adjusted(&self, verbosity: i8) -> Level {
+    const LEVELS: [Level; 5] = [
+      Level::Error,
+      Level::Warn,
+      Level::Info,
+      Level::Debug,
+      Level::Trace,
+    ];
+    let index = (*self as i8 + verbosity).clamp(0, LEVELS.len() as i8 - 1);
+    LEVELS[index as usize]
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+  /// Plain lines on stdout and stderr
+  Text,
+  /// One JSON object per line on stdout and stderr
+  Json,
+  /// Entries with structured fields sent straight to journald
+  Journald,
+}
+
+#[derive(Debug)]
+struct Settings {
+  level: Level,
+  /// Levels for specific modules, the longest matching module wins
+  modules: Vec<(String, Level)>,
+  format: Format,
+}
+
+static SETTINGS: RwLock<Settings> = RwLock::new(Settings {
+  level: Level::Info,
+  modules: Vec::new(),
+  format: Format::Text,
+});
+
+/// Sets the default level, the per module levels and the output format
+pub fn configure(level: Level, modules: Vec<(String, Level)>, format: Format) {
+  if let Ok(mut settings) = SETTINGS.write() {
+    *settings = Settings {
+      level,
+      modules,
+      format,
+    };
+  }
+}
+
+/// Strips the crate name from a module path, logs of the main loop in the crate root are main
+fn short_module(module: &str) -> &str {
+  if module == env!("CARGO_CRATE_NAME") {
+    return "main";
+  }
+  module.strip_prefix(CRATE_PREFIX).unwrap_or(module)
+}
+
+/// Keeps numeric fields as numbers in JSON output
+fn field_value(value: &str) -> Value {
+  if let Ok(number) = value.parse::<i64>() {
+    return json!(number);
+  }
+  match value.parse::<f64>() {
+    Ok(number) if number.is_finite() => json!(number),
+    _ => json!(value),
+  }
+}
+
+/// Whether entries of the level from the module would be written
+pub fn enabled(level: Level, module: &str) -> bool {
+  let settings = match SETTINGS.read() {
+    Ok(settings) => settings,
+    Err(_) => return level <= Level::Info,
+  };
+
+  let module = short_module(module);
+  let max_level = settings
+    .modules
+    .iter()
+    .filter(|(filter, _)| {
+      module == filter
+        || (module.starts_with(filter.as_str()) && module[filter.len()..].starts_with("::"))
+    })
+    .max_by_key(|(filter, _)| filter.len())
+    .map(|(_, level)| *level)
+    .unwrap_or(settings.level);
+
+  level <= max_level
+}
+
+/// Writes a log entry, use the `error!`, `warn!`, `info!`, `debug!` and `trace!` macros
+/// instead of calling this directly. Fields are kept as structured data in the JSON and
+/// journald formats, where journald gets their keys uppercased.
+pub fn log(level: Level, module: &str, fields: &[(&str, String)], message: fmt::Arguments) {
+  if !enabled(level, module) {
+    return;
+  }
+
+  let format = SETTINGS
+    .read()
+    .map(|settings| settings.format)
+    .unwrap_or(Format::Text);
+  let module = short_module(module);
+  let message = message.to_string();
+
+  let line = match format {
+    Format::Journald => {
+      let mut journal_fields: Vec<(String, String)> = fields
+        .iter()
+        .map(|(key, value)| (key.to_uppercase(), value.to_string()))
+        .collect();
+      journal_fields.push(("CODE_MODULE".to_string(), module.to_string()));
+
+      if systemd::journal_send(level.priority(), &message, &journal_fields).is_ok() {
+        return;
+      }
+      format!("[{} {}] {}", level.name().to_uppercase(), module, message)
+    }
+    Format::Json => {
+      let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default();
+
+      let mut entry = Map::new();
+      entry.insert("timestamp".to_string(), json!(timestamp));
+      entry.insert("level".to_string(), json!(level.name()));
+      entry.insert("module".to_string(), json!(module));
+      entry.insert("message".to_string(), json!(message));
+      for (key, value) in fields {
+        entry.insert(key.to_string(), field_value(value));
+      }
+      Value::Object(entry).to_string()
+    }
+    Format::Text => format!("[{} {}] {}", level.name().to_uppercase(), module, message),
+  };
+
+  match level {
+    Level::Error | Level::Warn => eprintln!("{}", line),
+    _ => println!("{}", line),
+  }
+}
+
+/// Logs a message, optionally with structured fields before a semicolon:
+/// `debug!(fetch_ms = 3; "Fetched in {}ms", 3)`
+macro_rules! log_at {
+  ($level:expr, $($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+    $crate::logger::log(
+      $level,
+      module_path!(),
+      &[$((stringify!($key), $value.to_string())),+],
+      format_args!($($arg)+),
+    )
+  };
+  ($level:expr, $($arg:tt)+) => {
+    $crate::logger::log($level, module_path!(), &[], format_args!($($arg)+))
+  };
+}
+
+macro_rules! error {
+  ($($arg:tt)+) => { log_at!($crate::logger::Level::Error, $($arg)+) };
+}
+
+macro_rules! warn {
+  ($($arg:tt)+) => { log_at!($crate::logger::Level::Warn, $($arg)+) };
+}
+
+macro_rules! info {
+  ($($arg:tt)+) => { log_at!($crate::logger::Level::Info, $($arg)+) };
+}
+
+macro_rules! debug {
+  ($($arg:tt)+) => { log_at!($crate::logger::Level::Debug, $($arg)+) };
+}
+
+macro_rules! trace {
+  ($($arg:tt)+) => { log_at!($crate::logger::Level::Trace, $($arg)+) };
+}
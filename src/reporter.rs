@@ -1,9 +1,19 @@
 use crate::arg_parser::ArgParser;
-use crate::config_manager::ConfigManager;
+use crate::config_manager::{Config, ConfigManager};
 use crate::data_collector::DataCollector;
+use crate::logger;
+use crate::systemd;
+use crate::telemetry::Telemetry;
 use crate::types::DynamicData;
 use crate::websocket_manager::{WebsocketEvent, WebsocketManager};
 use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+#[cfg(target_family = "unix")]
+use tokio::signal::unix::{signal, SignalKind};
 
 pub struct Reporter {
   pub data_collector: DataCollector,
@@ -12,6 +22,44 @@ pub struct Reporter {
   pub websocket_manager: Option<WebsocketManager>,
   pub args: ArgParser,
   pub dynamic_data: DynamicData,
+  pub reload_requested: Arc<AtomicBool>,
+  pub shutdown: Arc<Shutdown>,
+  pub telemetry: Telemetry,
+}
+
+/// Exit code used when the reporter stops because it was told to
+pub const EXIT_SHUTDOWN: i32 = 0;
+/// Exit code used when the reporter was told to stop but couldn't shut down cleanly
+pub const EXIT_SHUTDOWN_FAILED: i32 = 1;
+
+/// Tracks whether SIGTERM or SIGINT was received and wakes up the main loop when it is
+#[derive(Default)]
+pub struct Shutdown {
+  requested: AtomicBool,
+  notify: Notify,
+}
+
+impl Shutdown {
+  pub fn request(&self) {
+    // A second signal while already shutting down means the user doesn't want to wait
+    if self.requested.swap(true, Ordering::Relaxed) {
+      warn!("Received another shutdown signal, exiting immediately");
+      std::process::exit(EXIT_SHUTDOWN_FAILED);
+    }
+    info!("Shutting down after the current tick...");
+    self.notify.notify_one();
+  }
+
+  pub fn is_requested(&self) -> bool {
+    self.requested.load(Ordering::Relaxed)
+  }
+
+  /// Waits until a shutdown is requested
+  pub async fn requested(&self) {
+    if !self.is_requested() {
+      self.notify.notified().await;
+    }
+  }
 }
 
 impl Reporter {
@@ -20,9 +68,15 @@ impl Reporter {
     let websocket_manager: Option<WebsocketManager> = None;
 
     let config_manager: ConfigManager = ConfigManager::new()?;
-    let mut data_collector: DataCollector = DataCollector::new()?;
+    Reporter::configure_logger(&args, &config_manager);
+    let mut data_collector: DataCollector = DataCollector::new(&config_manager.config.collection)?;
     let version: String = env!("CARGO_PKG_VERSION").to_string();
-    let dynamic_data: DynamicData = data_collector.get_all_dynamic_data()?;
+    let dynamic_data: DynamicData = data_collector.get_all_dynamic_data().await?;
+
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    Reporter::listen_for_reload(reload_requested.clone())?;
+    let shutdown = Arc::new(Shutdown::default());
+    Reporter::listen_for_shutdown(shutdown.clone())?;
 
     let mut this = Self {
       data_collector,
@@ -31,16 +85,203 @@ impl Reporter {
       config_manager,
       args,
       dynamic_data,
+      reload_requested,
+      shutdown,
+      telemetry: Telemetry::default(),
     };
 
-    if !this.args.offline {
+    if !this.is_offline() {
       this.init_connection()?;
       this.send_static_data().await?;
     }
 
+    Reporter::notify_systemd(&format!("READY=1\nSTATUS={}", this.connection_status()));
+
     Ok(this)
   }
 
+  /// Flags the config for reloading whenever the process receives SIGHUP
+  #[cfg(target_family = "unix")]
+  fn listen_for_reload(reload_requested: Arc<AtomicBool>) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+      while hangup.recv().await.is_some() {
+        reload_requested.store(true, Ordering::Relaxed);
+      }
+    });
+    Ok(())
+  }
+
+  /// There's no SIGHUP on Windows, config file changes are still picked up
+  #[cfg(target_family = "windows")]
+  fn listen_for_reload(_reload_requested: Arc<AtomicBool>) -> Result<()> {
+    Ok(())
+  }
+
+  /// Requests a shutdown whenever the process receives SIGTERM or SIGINT
+  #[cfg(target_family = "unix")]
+  fn listen_for_shutdown(shutdown: Arc<Shutdown>) -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    tokio::spawn(async move {
+      loop {
+        tokio::select! {
+          _ = terminate.recv() => shutdown.request(),
+          _ = interrupt.recv() => shutdown.request(),
+        }
+      }
+    });
+    Ok(())
+  }
+
+  /// Requests a shutdown whenever Ctrl-C is pressed
+  #[cfg(target_family = "windows")]
+  fn listen_for_shutdown(shutdown: Arc<Shutdown>) -> Result<()> {
+    tokio::spawn(async move {
+      while tokio::signal::ctrl_c().await.is_ok() {
+        shutdown.request();
+      }
+    });
+    Ok(())
+  }
+
+  /// Lets the backend know the reporter is going offline and closes the connection
+  pub async fn disconnect(&mut self) -> Result<()> {
+    if let Some(mut websocket_manager) = self.websocket_manager.take() {
+      // The close frame is what the backend waits for, so it's sent even if this fails
+      let offline = websocket_manager.send(WebsocketEvent::Offline {
+        reason: "shutdown".to_string(),
+      });
+      if let Err(e) = offline {
+        warn!("Could not tell the backend we're going offline: {}", e);
+      }
+      websocket_manager.close()?;
+    }
+
+    Ok(())
+  }
+
+  /// Reloads the config if SIGHUP was received or the config file changed on disk,
+  /// reconnecting only when the connection settings are different.
+  /// An invalid config, or one that can't be applied, is reported and the current one is kept.
+  pub async fn reload_config_if_requested(&mut self) -> Result<()> {
+    let signalled = self.reload_requested.swap(false, Ordering::Relaxed);
+    if !signalled && !self.config_manager.has_changed_on_disk() {
+      return Ok(());
+    }
+
+    Reporter::notify_systemd("RELOADING=1");
+    let previous = match self.config_manager.reload() {
+      Ok(previous) => previous,
+      Err(e) => {
+        error!("Not reloading config, keeping the current one: {}", e);
+        Reporter::notify_systemd("READY=1");
+        return Ok(());
+      }
+    };
+
+    match self.apply_config_changes(&previous).await {
+      Ok(()) => info!("Reloaded config"),
+      Err(e) => {
+        error!(
+          "Could not apply the reloaded config, keeping the current one: {}",
+          e
+        );
+        let reloaded = std::mem::replace(&mut self.config_manager.config, previous);
+        if let Err(e) = self.apply_config_changes(&reloaded).await {
+          error!("Could not go back to the current config: {}", e);
+        }
+      }
+    }
+
+    Reporter::notify_systemd(&format!("READY=1\nSTATUS={}", self.connection_status()));
+    Ok(())
+  }
+
+  /// Brings logging, collection and the connection in line with the config
+  /// after it was changed from `previous`
+  async fn apply_config_changes(&mut self, previous: &Config) -> Result<()> {
+    let config = &self.config_manager.config;
+    if previous.log != config.log {
+      Reporter::configure_logger(&self.args, &self.config_manager);
+    }
+
+    if previous.collection != config.collection {
+      self.data_collector.apply_config(&config.collection)?;
+    }
+
+    if self.is_offline() {
+      self.websocket_manager = None;
+    } else if self.websocket_manager.is_none() || previous.connection_changed(config) {
+      info!("Connection settings changed, reconnecting");
+      self.telemetry.reconnects += 1;
+      self.init_connection()?;
+      self.send_static_data().await?;
+    }
+
+    Ok(())
+  }
+
+  /// Sets up logging from the config, with the launch parameters taking precedence
+  fn configure_logger(args: &ArgParser, config_manager: &ConfigManager) {
+    let log = &config_manager.config.log;
+    logger::configure(
+      log.level.adjusted(args.verbosity),
+      log
+        .modules
+        .iter()
+        .map(|(module, level)| (module.clone(), *level))
+        .collect(),
+      args.log_format.unwrap_or(log.format),
+    );
+  }
+
+  /// A short description of the connection for systemd's status line
+  pub fn connection_status(&self) -> String {
+    let backend_hostname = &self.config_manager.config.backend_hostname;
+    match self.websocket_manager {
+      _ if self.is_offline() => "Offline, not sending data".to_string(),
+      Some(_) => format!("Connected to {}", backend_hostname),
+      None => format!("Not connected to {}", backend_hostname),
+    }
+  }
+
+  /// Updates systemd's status line with the result of the last send
+  pub fn notify_status(&self, send_result: &Result<()>, send_elapsed: Duration) {
+    let status = match send_result {
+      Ok(_) if self.is_offline() => self.connection_status(),
+      Ok(_) => format!(
+        "{}, last send took {}ms",
+        self.connection_status(),
+        send_elapsed.as_millis()
+      ),
+      Err(e) => format!("{}, last send failed: {}", self.connection_status(), e),
+    };
+
+    Reporter::notify_systemd(&format!("STATUS={}", status));
+  }
+
+  /// Tells systemd about a state change, an error is only logged
+  /// since the reporter works the same without it
+  fn notify_systemd(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+      error!("Error while notifying systemd: {}", e);
+    }
+  }
+
+  /// The data collection interval in seconds
+  pub fn interval(&self) -> f64 {
+    self
+      .args
+      .interval
+      .unwrap_or(self.config_manager.config.collection.interval)
+  }
+
+  /// Whether sending data to the backend is disabled
+  pub fn is_offline(&self) -> bool {
+    self.args.offline || self.config_manager.config.collection.offline
+  }
+
   pub fn init_connection(&mut self) -> Result<()> {
     let websocket_url: String = format!(
       "wss://{}/reporter",
@@ -61,9 +302,22 @@ impl Reporter {
     Ok(())
   }
 
+  /// Sends the static data again once its interval has passed since it was last sent
+  pub async fn send_static_data_if_due(&mut self) -> Result<()> {
+    let interval = self.data_collector.intervals.statics;
+    if self.websocket_manager.is_some() && self.data_collector.schedule.is_due("statics", interval)
+    {
+      debug!("Sending static data again");
+      self.send_static_data().await?;
+    }
+    Ok(())
+  }
+
   pub async fn send_static_data(&mut self) -> Result<()> {
     if let Some(websocket_manager) = self.websocket_manager.as_mut() {
       let static_data = self.data_collector.get_statics().await?;
+      let interval = self.data_collector.intervals.statics;
+      self.data_collector.schedule.mark_run("statics", interval);
 
       websocket_manager.send(WebsocketEvent::StaticData {
         hostname: static_data.hostname,
@@ -79,6 +333,9 @@ impl Reporter {
         cpu_threads: static_data.cpu_threads,
         total_mem: static_data.total_mem,
         reporter_version: self.version.clone(),
+        container: static_data.container,
+        virtualization: static_data.virtualization,
+        extra: static_data.extra,
       })?;
     }
 
@@ -86,27 +343,62 @@ impl Reporter {
   }
 
   pub async fn update_dynamic_data(&mut self) -> Result<()> {
-    self.dynamic_data = self.data_collector.get_all_dynamic_data()?;
+    let dynamic_data = match self.data_collector.get_all_dynamic_data().await {
+      Ok(dynamic_data) => dynamic_data,
+      Err(e) => {
+        self.telemetry.dropped_samples += 1;
+        return Err(e);
+      }
+    };
+    self.dynamic_data = dynamic_data;
     self.data_collector.increment_iterator_index();
+
+    for (collector, time) in &self.data_collector.last_collection_times {
+      self.telemetry.record_collection(collector, *time);
+    }
+
+    if self.data_collector.collectors.reporter {
+      let (memory, cpu_usage) = self.data_collector.get_reporter_process()?;
+      let queue_depth = self
+        .websocket_manager
+        .as_ref()
+        .and_then(WebsocketManager::queued_bytes);
+      self.dynamic_data.reporter = Some(self.telemetry.stats(memory, cpu_usage, queue_depth));
+    }
     Ok(())
   }
 
   pub async fn send_dynamic_data(&mut self) -> Result<()> {
     if let Some(websocket_manager) = self.websocket_manager.as_mut() {
       let dd = self.dynamic_data.clone();
-      if let Err(e) = websocket_manager.send(WebsocketEvent::DynamicData {
+      let send_start_time = Instant::now();
+      let send_result = websocket_manager.send(WebsocketEvent::DynamicData {
         cpu: dd.cpu,
         ram: dd.ram,
         swap: dd.swap,
         gpu: dd.gpu,
         process_count: dd.process_count,
         disks: dd.disks,
+        network_disks: dd.network_disks,
         temps: dd.temps,
         network: dd.network,
+        top_processes: dd.top_processes,
+        pressure: dd.pressure,
+        cgroups: dd.cgroups,
+        block_devices: dd.block_devices,
+        systemd: dd.systemd,
         host_uptime: dd.host_uptime,
         reporter_uptime: dd.reporter_uptime,
-      }) {
-        eprintln!("Websocket error: {}", e);
+        reporter: dd.reporter,
+        errors: dd.errors,
+        extra: dd.extra,
+      });
+      self.telemetry.record_send(send_start_time.elapsed());
+
+      if let Err(e) = send_result {
+        error!("Websocket error: {}", e);
+        self.telemetry.dropped_samples += 1;
+        self.telemetry.reconnects += 1;
         self.init_connection()?;
         self.send_static_data().await?;
       }
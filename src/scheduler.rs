@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// The number of the wall-clock aligned slot of `interval` seconds that `time` falls in,
+/// e.g. with a 30s interval every :00 and :30 of a minute starts a new slot
+fn slot(time: SystemTime, interval: f64) -> u64 {
+  let since_epoch = time
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs_f64();
+  (since_epoch / interval).floor() as u64
+}
+
+/// Schedules ticks on wall-clock boundaries of the interval so they don't drift
+/// no matter how long each tick takes
+#[derive(Debug)]
+pub struct Scheduler {
+  interval: f64,
+  last_slot: Option<u64>,
+}
+
+impl Scheduler {
+  pub fn new(interval: f64) -> Self {
+    Self {
+      interval,
+      last_slot: None,
+    }
+  }
+
+  /// Changes the interval, e.g. after the config was reloaded
+  pub fn set_interval(&mut self, interval: f64) {
+    if interval != self.interval {
+      self.interval = interval;
+      self.last_slot = Some(slot(SystemTime::now(), interval));
+    }
+  }
+
+  /// Marks the start of a tick and returns how many ticks were skipped
+  /// because the previous one ran past their boundaries
+  pub fn start_tick(&mut self) -> u64 {
+    let current = slot(SystemTime::now(), self.interval);
+    let skipped = match self.last_slot {
+      Some(last) => current.saturating_sub(last).saturating_sub(1),
+      None => 0,
+    };
+    self.last_slot = Some(current);
+    skipped
+  }
+
+  /// How long to wait until the next boundary of the interval
+  pub fn time_until_next_tick(&self) -> Duration {
+    let now = SystemTime::now();
+    let next = (slot(now, self.interval) + 1) as f64 * self.interval;
+    let since_epoch = now
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_secs_f64();
+    Duration::from_secs_f64((next - since_epoch).max(0.0))
+  }
+}
+
+/// Keeps track of when each collector last ran so collectors with their own interval
+/// only run once per wall-clock aligned slot of it
+#[derive(Debug, Default)]
+pub struct CollectorSchedule {
+  last_slots: HashMap<String, u64>,
+}
+
+impl CollectorSchedule {
+  /// Whether the collector should run this tick, which it does until a run in the current
+  /// slot succeeded and was counted with `mark_run`, so a failed run is retried on the next tick.
+  /// Collectors without an interval of their own run every tick.
+  pub fn is_due(&self, collector: &str, interval: Option<f64>) -> bool {
+    match interval {
+      Some(interval) => self.last_slots.get(collector) != Some(&slot(SystemTime::now(), interval)),
+      None => true,
+    }
+  }
+
+  /// Counts the collector as run in the current slot of its interval
+  pub fn mark_run(&mut self, collector: &str, interval: Option<f64>) {
+    if let Some(interval) = interval {
+      let current = slot(SystemTime::now(), interval);
+      self.last_slots.insert(collector.to_string(), current);
+    }
+  }
+
+  /// Makes every collector run on the next tick
+  pub fn reset(&mut self) {
+    self.last_slots.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn collectors_are_due_until_a_run_is_marked() {
+    let mut schedule = CollectorSchedule::default();
+    assert!(schedule.is_due("disks", Some(3600.0)));
+    // A failed run isn't marked, so it's tried again
+    assert!(schedule.is_due("disks", Some(3600.0)));
+
+    schedule.mark_run("disks", Some(3600.0));
+    assert!(!schedule.is_due("disks", Some(3600.0)));
+    assert!(schedule.is_due("statics", Some(3600.0)));
+
+    schedule.reset();
+    assert!(schedule.is_due("disks", Some(3600.0)));
+  }
+
+  #[test]
+  fn collectors_without_an_interval_are_always_due() {
+    let mut schedule = CollectorSchedule::default();
+    schedule.mark_run("cpu", None);
+    assert!(schedule.is_due("cpu", None));
+  }
+}
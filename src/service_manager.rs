@@ -0,0 +1,528 @@
+use anyhow::{anyhow, Result};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::PermissionsExt;
+
+pub const SERVICE_NAME: &str = "xornet-reporter";
+pub const SERVICE_USER: &str = "xornet";
+pub const INSTALL_DIR: &str = "/opt/xornet";
+
+const SYSTEMD_UNIT_PATH: &str = "/etc/systemd/system/xornet-reporter.service";
+const OPENRC_SCRIPT_PATH: &str = "/etc/init.d/xornet-reporter";
+
+/// Reading other users' files under /proc and the container runtimes' metadata
+/// needs these, everything else runs without privileges
+const CAPABILITIES: [&str; 2] = ["CAP_DAC_READ_SEARCH", "CAP_SYS_PTRACE"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InitSystem {
+  Systemd,
+  OpenRC,
+}
+
+impl InitSystem {
+  /// Detects the init system the same way the install script used to
+  pub fn detect() -> Option<Self> {
+    let init_name = fs::read_to_string("/proc/1/comm").unwrap_or_default();
+    if init_name.trim() == "systemd" {
+      Some(InitSystem::Systemd)
+    } else if Path::new("/sbin/openrc").exists() {
+      Some(InitSystem::OpenRC)
+    } else {
+      None
+    }
+  }
+
+  pub fn from_name(name: &str) -> Result<Self> {
+    match name {
+      "systemd" => Ok(InitSystem::Systemd),
+      "openrc" => Ok(InitSystem::OpenRC),
+      _ => Err(anyhow!(
+        "Unknown init system \"{}\", use systemd or openrc",
+        name
+      )),
+    }
+  }
+
+  fn service_path(&self) -> &'static str {
+    match self {
+      InitSystem::Systemd => SYSTEMD_UNIT_PATH,
+      InitSystem::OpenRC => OPENRC_SCRIPT_PATH,
+    }
+  }
+}
+
+/// A single step of installing or uninstalling the service
+#[derive(Debug)]
+enum Action {
+  WriteFile {
+    path: String,
+    contents: String,
+    mode: u32,
+  },
+  RemoveFile {
+    path: String,
+  },
+  Run {
+    program: String,
+    args: Vec<String>,
+    /// Whether a failure should abort the remaining steps
+    required: bool,
+  },
+}
+
+impl Action {
+  fn run(program: &str, args: &[&str], required: bool) -> Self {
+    Action::Run {
+      program: program.to_string(),
+      args: args.iter().map(|arg| arg.to_string()).collect(),
+      required,
+    }
+  }
+}
+
+/// What a dry run prints instead of carrying out the action
+impl fmt::Display for Action {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Action::WriteFile {
+        path,
+        contents,
+        mode,
+      } => write!(f, "# Would write {} (mode {:o}):\n{}", path, mode, contents),
+      Action::RemoveFile { path } => write!(f, "# Would remove {}", path),
+      Action::Run { program, args, .. } => {
+        write!(f, "# Would run: {} {}", program, args.join(" "))
+      }
+    }
+  }
+}
+
+/// Installs, uninstalls and inspects the reporter's system service
+#[derive(Debug)]
+pub struct ServiceManager {
+  pub init_system: InitSystem,
+  pub dry_run: bool,
+  pub binary_path: String,
+}
+
+impl ServiceManager {
+  pub fn new(init_system: Option<InitSystem>, dry_run: bool) -> Result<Self> {
+    if env::consts::OS != "linux" {
+      return Err(anyhow!("Services can only be managed on Linux"));
+    }
+
+    let init_system = match init_system.or_else(InitSystem::detect) {
+      Some(init_system) => init_system,
+      None => {
+        return Err(anyhow!(
+          "Neither systemd nor OpenRC was found, pass --init systemd|openrc to pick one"
+        ))
+      }
+    };
+
+    Ok(Self {
+      init_system,
+      dry_run,
+      binary_path: format!("{}/{}", INSTALL_DIR, SERVICE_NAME),
+    })
+  }
+
+  pub fn install(&self) -> Result<()> {
+    self.execute(self.install_actions()?)?;
+    if !self.dry_run {
+      println!("Xornet reporter service installed and started");
+    }
+    Ok(())
+  }
+
+  fn install_actions(&self) -> Result<Vec<Action>> {
+    let mut actions = self.create_user_actions();
+
+    let current_binary = env::current_exe()?.to_string_lossy().to_string();
+    if current_binary != self.binary_path {
+      // install replaces the file instead of writing into it so a running binary can be updated
+      actions.push(Action::run(
+        "install",
+        &["-D", "-m", "755", &current_binary, &self.binary_path],
+        true,
+      ));
+    }
+
+    // The reporter keeps its config and secrets in its working directory
+    actions.push(Action::run(
+      "chown",
+      &["-R", &format!("{0}:{0}", SERVICE_USER), INSTALL_DIR],
+      true,
+    ));
+
+    match self.init_system {
+      InitSystem::Systemd => {
+        actions.push(Action::WriteFile {
+          path: SYSTEMD_UNIT_PATH.to_string(),
+          contents: self.systemd_unit(),
+          mode: 0o644,
+        });
+        actions.push(Action::run("systemctl", &["daemon-reload"], true));
+        actions.push(Action::run(
+          "systemctl",
+          &["enable", "--now", "xornet-reporter.service"],
+          true,
+        ));
+      }
+      InitSystem::OpenRC => {
+        actions.push(Action::WriteFile {
+          path: OPENRC_SCRIPT_PATH.to_string(),
+          contents: self.openrc_script(),
+          mode: 0o755,
+        });
+        actions.push(Action::run(
+          "rc-update",
+          &["add", SERVICE_NAME, "default"],
+          true,
+        ));
+        actions.push(Action::run("rc-service", &[SERVICE_NAME, "start"], true));
+      }
+    }
+    Ok(actions)
+  }
+
+  /// Removes the service but keeps the install directory with the config and the user
+  pub fn uninstall(&self) -> Result<()> {
+    let mut actions = self.uninstall_actions();
+    if !Path::new(self.init_system.service_path()).exists() && !self.dry_run {
+      actions.clear();
+      println!("Xornet reporter service is not installed");
+    }
+
+    self.execute(actions)?;
+    if !self.dry_run {
+      println!(
+        "Xornet reporter service removed, {} and the {} user were kept",
+        INSTALL_DIR, SERVICE_USER
+      );
+    }
+    Ok(())
+  }
+
+  fn uninstall_actions(&self) -> Vec<Action> {
+    match self.init_system {
+      InitSystem::Systemd => vec![
+        Action::run(
+          "systemctl",
+          &["disable", "--now", "xornet-reporter.service"],
+          false,
+        ),
+        Action::RemoveFile {
+          path: SYSTEMD_UNIT_PATH.to_string(),
+        },
+        Action::run("systemctl", &["daemon-reload"], false),
+      ],
+      InitSystem::OpenRC => vec![
+        Action::run("rc-service", &[SERVICE_NAME, "stop"], false),
+        Action::run("rc-update", &["delete", SERVICE_NAME, "default"], false),
+        Action::RemoveFile {
+          path: OPENRC_SCRIPT_PATH.to_string(),
+        },
+      ],
+    }
+  }
+
+  /// Prints the state of the service and returns the exit code of the init
+  /// system's status command, which isn't 0 when the service isn't running
+  pub fn status(&self) -> Result<i32> {
+    let service_path = self.init_system.service_path();
+    println!("Init system: {:?}", self.init_system);
+    println!(
+      "Service file: {} ({})",
+      service_path,
+      if Path::new(service_path).exists() {
+        "installed"
+      } else {
+        "not installed"
+      }
+    );
+
+    let status = match self.init_system {
+      InitSystem::Systemd => Command::new("systemctl")
+        .args(["status", "--no-pager", "xornet-reporter.service"])
+        .status()?,
+      InitSystem::OpenRC => Command::new("rc-service")
+        .args([SERVICE_NAME, "status"])
+        .status()?,
+    };
+
+    Ok(status.code().unwrap_or(1))
+  }
+
+  /// Adds a system user without a login shell or home to run the service as
+  fn create_user_actions(&self) -> Vec<Action> {
+    let user_exists = Command::new("id")
+      .args(["-u", SERVICE_USER])
+      .output()
+      .map(|output| output.status.success())
+      .unwrap_or(false);
+
+    if user_exists {
+      return vec![];
+    }
+
+    match self.init_system {
+      // Alpine's busybox adduser doesn't know the useradd flags
+      InitSystem::OpenRC if !Path::new("/usr/sbin/useradd").exists() => vec![Action::run(
+        "adduser",
+        &[
+          "-S",
+          "-D",
+          "-H",
+          "-h",
+          INSTALL_DIR,
+          "-s",
+          "/sbin/nologin",
+          SERVICE_USER,
+        ],
+        true,
+      )],
+      _ => vec![Action::run(
+        "useradd",
+        &[
+          "--system",
+          "--no-create-home",
+          "--home-dir",
+          INSTALL_DIR,
+          "--shell",
+          "/usr/sbin/nologin",
+          SERVICE_USER,
+        ],
+        true,
+      )],
+    }
+  }
+
+  /// The systemd unit, hardened as far as collecting system stats allows.
+  /// /dev is left alone so NVML can still reach the GPU. The two capabilities let
+  /// the reporter read other users' /proc/<pid>/io and /proc/1/environ and the
+  /// container runtimes' metadata, dropping them only loses those.
+  pub fn systemd_unit(&self) -> String {
+    format!(
+      "[Unit]
+Description=Xornet reporter
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=notify
+NotifyAccess=main
+WatchdogSec=60
+User={user}
+Group={user}
+WorkingDirectory={dir}
+ExecStart={binary} --journald
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=always
+RestartSec=10
+UMask=0077
+
+NoNewPrivileges=yes
+CapabilityBoundingSet={capabilities}
+AmbientCapabilities={capabilities}
+ProtectSystem=strict
+ReadWritePaths={dir}
+ProtectHome=read-only
+PrivateTmp=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectKernelLogs=yes
+ProtectControlGroups=yes
+ProtectClock=yes
+ProtectHostname=yes
+RestrictAddressFamilies=AF_UNIX AF_INET AF_INET6 AF_NETLINK
+RestrictNamespaces=yes
+RestrictRealtime=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+MemoryDenyWriteExecute=yes
+SystemCallArchitectures=native
+
+[Install]
+WantedBy=multi-user.target
+",
+      user = SERVICE_USER,
+      dir = INSTALL_DIR,
+      binary = self.binary_path,
+      capabilities = CAPABILITIES.join(" "),
+    )
+  }
+
+  /// The OpenRC script, the capabilities need OpenRC 0.45 or later
+  pub fn openrc_script(&self) -> String {
+    format!(
+      "#!/sbin/openrc-run
+name=\"Xornet Reporter service\"
+description=\"Xornet Reporter is a service for Xornet status reporting\"
+
+command={binary}
+command_user=\"{user}:{user}\"
+directory=\"{dir}\"
+pidfile=/run/xornet-reporter.pid
+supervisor=supervise-daemon
+supervise_daemon_args=\"--respawn-delay 5\"
+umask=077
+no_new_privs=yes
+capabilities=\"{capabilities}\"
+extra_started_commands=\"reload\"
+
+depend() {{
+  need net
+  after localmount
+}}
+
+reload() {{
+  ebegin \"Reloading $name\"
+  supervise-daemon \"$RC_SVCNAME\" --signal HUP
+  eend $?
+}}
+",
+      user = SERVICE_USER,
+      dir = INSTALL_DIR,
+      binary = self.binary_path,
+      capabilities = CAPABILITIES
+        .iter()
+        .map(|capability| format!("^{}", capability.to_lowercase()))
+        .collect::<Vec<_>>()
+        .join(","),
+    )
+  }
+
+  /// Carries out the actions or only prints them when doing a dry run
+  fn execute(&self, actions: Vec<Action>) -> Result<()> {
+    for action in actions {
+      if self.dry_run {
+        println!("{}", action);
+        continue;
+      }
+
+      match action {
+        Action::WriteFile {
+          path,
+          contents,
+          mode,
+        } => {
+          fs::write(&path, contents)?;
+          #[cfg(target_family = "unix")]
+          fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+        }
+        Action::RemoveFile { path } => {
+          if Path::new(&path).exists() {
+            fs::remove_file(&path)?;
+          }
+        }
+        Action::Run {
+          program,
+          args,
+          required,
+        } => {
+          let status = Command::new(&program).args(&args).status()?;
+          if !status.success() {
+            let message = format!("{} {} failed with {}", program, args.join(" "), status);
+            if required {
+              return Err(anyhow!(message));
+            }
+            eprintln!("{}", message);
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn manager(init_system: InitSystem) -> ServiceManager {
+    ServiceManager {
+      init_system,
+      dry_run: true,
+      binary_path: format!("{}/{}", INSTALL_DIR, SERVICE_NAME),
+    }
+  }
+
+  fn dry_run(actions: &[Action]) -> Vec<String> {
+    actions.iter().map(ToString::to_string).collect()
+  }
+
+  #[test]
+  fn systemd_install_writes_the_unit_and_starts_it() {
+    let output = dry_run(&manager(InitSystem::Systemd).install_actions().unwrap());
+
+    // Creating the user depends on whether it exists on the machine running the tests
+    assert!(output
+      .iter()
+      .any(|line| line.starts_with("# Would run: install -D -m 755 ")
+        && line.ends_with(" /opt/xornet/xornet-reporter")));
+    let steps = &output[output.len() - 4..];
+    assert_eq!(steps[0], "# Would run: chown -R xornet:xornet /opt/xornet");
+    assert!(steps[1].starts_with(
+      "# Would write /etc/systemd/system/xornet-reporter.service (mode 644):\n[Unit]\n"
+    ));
+    assert_eq!(steps[2], "# Would run: systemctl daemon-reload");
+    assert_eq!(
+      steps[3],
+      "# Would run: systemctl enable --now xornet-reporter.service"
+    );
+  }
+
+  #[test]
+  fn openrc_install_writes_the_script_and_starts_it() {
+    let output = dry_run(&manager(InitSystem::OpenRC).install_actions().unwrap());
+
+    let steps = &output[output.len() - 3..];
+    assert!(steps[0]
+      .starts_with("# Would write /etc/init.d/xornet-reporter (mode 755):\n#!/sbin/openrc-run\n"));
+    assert_eq!(
+      steps[1],
+      "# Would run: rc-update add xornet-reporter default"
+    );
+    assert_eq!(steps[2], "# Would run: rc-service xornet-reporter start");
+  }
+
+  #[test]
+  fn uninstall_removes_the_service_only() {
+    assert_eq!(
+      dry_run(&manager(InitSystem::Systemd).uninstall_actions()),
+      [
+        "# Would run: systemctl disable --now xornet-reporter.service",
+        "# Would remove /etc/systemd/system/xornet-reporter.service",
+        "# Would run: systemctl daemon-reload",
+      ]
+    );
+    assert_eq!(
+      dry_run(&manager(InitSystem::OpenRC).uninstall_actions()),
+      [
+        "# Would run: rc-service xornet-reporter stop",
+        "# Would run: rc-update delete xornet-reporter default",
+        "# Would remove /etc/init.d/xornet-reporter",
+      ]
+    );
+  }
+
+  #[test]
+  fn services_run_unprivileged_with_the_capabilities_collectors_need() {
+    let unit = manager(InitSystem::Systemd).systemd_unit();
+    assert!(unit.contains("\nUser=xornet\n"));
+    assert!(unit.contains("\nExecStart=/opt/xornet/xornet-reporter --journald\n"));
+    assert!(unit.contains("\nCapabilityBoundingSet=CAP_DAC_READ_SEARCH CAP_SYS_PTRACE\n"));
+    assert!(unit.contains("\nAmbientCapabilities=CAP_DAC_READ_SEARCH CAP_SYS_PTRACE\n"));
+
+    let script = manager(InitSystem::OpenRC).openrc_script();
+    assert!(script.contains("\ncommand_user=\"xornet:xornet\"\n"));
+    assert!(script.contains("\ncapabilities=\"^cap_dac_read_search,^cap_sys_ptrace\"\n"));
+  }
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use std::{
+  env,
+  os::linux::net::SocketAddrExt,
+  os::unix::ffi::OsStrExt,
+  os::unix::net::{SocketAddr, UnixDatagram},
+};
+
+#[cfg(target_os = "linux")]
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Sends a state change like `READY=1` to systemd when running under a `Type=notify` unit,
+/// does nothing when `NOTIFY_SOCKET` isn't set
+#[cfg(target_os = "linux")]
+pub fn notify(state: &str) -> Result<()> {
+  let path = match env::var_os("NOTIFY_SOCKET") {
+    Some(path) => path,
+    None => return Ok(()),
+  };
+
+  let socket = UnixDatagram::unbound()?;
+
+  // Paths starting with @ are in the abstract namespace
+  match path.as_bytes().strip_prefix(b"@") {
+    Some(name) => {
+      socket.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?)?;
+    }
+    None => {
+      socket.send_to(state.as_bytes(), &path)?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn notify(_state: &str) -> Result<()> {
+  Ok(())
+}
+
+/// Writes an entry to journald using its native protocol so the fields stay structured
+#[cfg(target_os = "linux")]
+pub fn journal_send(priority: u8, message: &str, fields: &[(String, String)]) -> Result<()> {
+  let mut entry = Vec::new();
+  append_journal_field(&mut entry, "PRIORITY", &priority.to_string());
+  append_journal_field(&mut entry, "SYSLOG_IDENTIFIER", env!("CARGO_PKG_NAME"));
+  append_journal_field(&mut entry, "MESSAGE", message);
+  for (key, value) in fields {
+    append_journal_field(&mut entry, key, value);
+  }
+
+  UnixDatagram::unbound()?.send_to(&entry, JOURNAL_SOCKET)?;
+  Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn journal_send(_priority: u8, _message: &str, _fields: &[(String, String)]) -> Result<()> {
+  Err(anyhow::anyhow!("journald is only available on Linux"))
+}
+
+/// Values with newlines have to be sent with their length in front instead of after an `=`
+#[cfg(target_os = "linux")]
+fn append_journal_field(entry: &mut Vec<u8>, key: &str, value: &str) {
+  entry.extend_from_slice(key.as_bytes());
+  if value.contains('\n') {
+    entry.push(b'\n');
+    entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+  } else {
+    entry.push(b'=');
+  }
+  entry.extend_from_slice(value.as_bytes());
+  entry.push(b'\n');
+}
+
+/// Pings the systemd watchdog when the unit has `WatchdogSec=` set
+#[derive(Debug)]
+pub struct Watchdog {
+  interval: Option<Duration>,
+}
+
+impl Watchdog {
+  /// Reads the watchdog timeout systemd passed us, pinging at half of it to be safe
+  pub fn from_env() -> Self {
+    let pid_matches = std::env::var("WATCHDOG_PID")
+      .map(|pid| pid == std::process::id().to_string())
+      .unwrap_or(true);
+
+    let interval = std::env::var("WATCHDOG_USEC")
+      .ok()
+      .and_then(|usec| usec.parse::<u64>().ok())
+      .filter(|usec| pid_matches && *usec > 0)
+      .map(|usec| Duration::from_micros(usec / 2));
+
+    Self { interval }
+  }
+
+  pub fn ping(&self) {
+    if self.interval.is_some() {
+      if let Err(e) = notify("WATCHDOG=1") {
+        error!("Error while pinging the systemd watchdog: {}", e);
+      }
+    }
+  }
+
+  /// Sleeps for the given duration while still pinging the watchdog in time
+  pub async fn sleep(&self, duration: Duration) {
+    let interval = match self.interval {
+      Some(interval) => interval,
+      None => return tokio::time::sleep(duration).await,
+    };
+
+    let mut remaining = duration;
+    while !remaining.is_zero() {
+      let step = remaining.min(interval);
+      tokio::time::sleep(step).await;
+      remaining -= step;
+      self.ping();
+    }
+  }
+}
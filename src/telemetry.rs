@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::types::{LatencyHistogram, ReporterStats, HISTOGRAM_BOUNDS_MS};
+
+impl LatencyHistogram {
+  pub fn record(&mut self, duration: Duration) {
+    if self.buckets.is_empty() {
+      self.buckets = vec![0; HISTOGRAM_BOUNDS_MS.len() + 1];
+    }
+
+    let millis = duration.as_secs_f64() * 1000.0;
+    let bucket = HISTOGRAM_BOUNDS_MS
+      .iter()
+      .position(|bound| millis <= *bound as f64)
+      .unwrap_or(HISTOGRAM_BOUNDS_MS.len());
+
+    self.buckets[bucket] += 1;
+    self.count += 1;
+    self.sum_ms += millis;
+    self.max_ms = self.max_ms.max(millis);
+    self.last_ms = millis;
+  }
+}
+
+/// Keeps track of the reporter's own performance since it started
+#[derive(Debug, Default)]
+pub struct Telemetry {
+  pub collection: BTreeMap<String, LatencyHistogram>,
+  pub send: LatencyHistogram,
+  pub reconnects: u64,
+  pub dropped_samples: u64,
+  pub overruns: u64,
+  pub skipped_ticks: u64,
+}
+
+impl Telemetry {
+  pub fn record_collection(&mut self, collector: &str, duration: Duration) {
+    self
+      .collection
+      .entry(collector.to_string())
+      .or_default()
+      .record(duration);
+  }
+
+  pub fn record_send(&mut self, duration: Duration) {
+    self.send.record(duration);
+  }
+
+  /// Counts the tick as an overrun if it took longer than the interval
+  pub fn record_tick(&mut self, elapsed: Duration, interval: f64) {
+    if elapsed.as_secs_f64() > interval {
+      self.overruns += 1;
+    }
+  }
+
+  pub fn stats(&self, memory: u64, cpu_usage: f32, queue_depth: Option<u64>) -> ReporterStats {
+    ReporterStats {
+      collection: self.collection.clone(),
+      send: self.send.clone(),
+      reconnects: self.reconnects,
+      dropped_samples: self.dropped_samples,
+      queue_depth,
+      overruns: self.overruns,
+      skipped_ticks: self.skipped_ticks,
+      memory,
+      cpu_usage,
+    }
+  }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StaticData {
@@ -15,19 +16,50 @@ pub struct StaticData {
   pub cpu_threads: usize,
   pub total_mem: u64,
   pub reporter_version: String,
+  /// The container runtime the reporter runs in, like docker or kubernetes
+  pub container: Option<String>,
+  /// The hypervisor the machine runs on, like kvm or vmware
+  pub virtualization: Option<String>,
+  /// Static info of collectors that have any, by collector name
+  pub extra: BTreeMap<String, serde_json::Value>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DynamicData {
-  pub cpu: CPUStats,
-  pub ram: RAMStats,
+  pub cpu: Option<CPUStats>,
+  pub ram: Option<RAMStats>,
   pub gpu: Option<GPUStats>,
-  pub process_count: i32,
-  pub swap: SwapStats,
-  pub disks: Vec<DiskStats>,
+  pub process_count: Option<i32>,
+  pub swap: Option<SwapStats>,
+  pub disks: Option<Vec<DiskStats>>,
+  pub network_disks: Option<Vec<DiskStats>>,
   pub temps: Option<Vec<TempStats>>,
-  pub network: Vec<NetworkInterfaceStats>,
+  pub network: Option<Vec<NetworkInterfaceStats>>,
+  pub top_processes: Option<Vec<ProcessStats>>,
+  pub pressure: Option<PressureStats>,
+  pub cgroups: Option<Vec<CgroupStats>>,
+  pub block_devices: Option<Vec<BlockDeviceStats>>,
+  pub systemd: Option<SystemdStats>,
   pub host_uptime: u64,
   pub reporter_uptime: u64,
+  pub reporter: Option<ReporterStats>,
+  /// Why collectors that should have been in the sample are missing
+  pub errors: BTreeMap<String, CollectorError>,
+  /// Values of collectors that aren't built in, by collector name
+  pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Why a collector is missing from a sample
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CollectorError {
+  /// A stable code like "nvml_not_loaded", "no_sensors", "timeout" or "failed"
+  pub code: String,
+  pub message: String,
+  /// Exit code of a failed exec collector's command, missing if it was killed by a signal
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub exit_status: Option<i32>,
+  /// What a failed exec collector's command wrote to stderr
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub stderr: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,18 +74,93 @@ pub struct NetworkInterfaceStats {
 pub struct CPUStats {
   pub usage: Vec<u16>,
   pub freq: Vec<u16>,
+  /// Missing on Windows
+  pub load_average: Option<LoadAverage>,
+  /// Linux only, like the per core times and scheduler stats
+  pub times: Option<CpuTimes>,
+  pub core_times: Option<Vec<CpuTimes>>,
+  pub scheduler: Option<SchedulerStats>,
+  /// Percent of the cores the reporter's container may use that it used, with
+  /// scope_to_cgroup on and in a container on Linux
+  pub container_usage: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoadAverage {
+  pub one: f64,
+  pub five: f64,
+  pub fifteen: f64,
+}
+
+/// Percentages of time spent in each state since the last run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CpuTimes {
+  pub user: f32,
+  pub nice: f32,
+  pub system: f32,
+  pub idle: f32,
+  pub iowait: f32,
+  pub irq: f32,
+  pub softirq: f32,
+  /// Time a hypervisor gave to other guests, what's missing on an overcommitted VM
+  pub steal: f32,
+  /// Time spent running guests, also counted in user
+  pub guest: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SchedulerStats {
+  /// Per second since the last run
+  pub context_switches: f64,
+  pub interrupts: f64,
+  pub forks: f64,
+  /// Processes that are runnable and blocked on I/O right now
+  pub running: u64,
+  pub blocked: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RAMStats {
   pub used: u64,
   pub total: u64,
+  /// Linux only
+  pub details: Option<MemoryDetails>,
+}
+
+/// The breakdown of /proc/meminfo in KB like the rest of RAMStats, with /proc/vmstat's
+/// page faults and OOM kills
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemoryDetails {
+  pub available: u64,
+  pub free: u64,
+  pub buffers: u64,
+  pub cached: u64,
+  pub shared: u64,
+  pub slab_reclaimable: u64,
+  pub slab_unreclaimable: u64,
+  pub dirty: u64,
+  pub writeback: u64,
+  /// Counted in huge pages rather than KB
+  pub huge_pages_total: u64,
+  pub huge_pages_free: u64,
+  pub huge_page_size: u64,
+  /// Memory that would be needed if every allocation was used
+  pub committed: u64,
+  pub commit_limit: u64,
+  /// Per second since the last run
+  pub page_faults: Option<f64>,
+  pub major_page_faults: Option<f64>,
+  /// Processes killed for running out of memory since boot, Linux 4.13 and later
+  pub oom_kills: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SwapStats {
   pub used: u64,
   pub total: u64,
+  /// Pages swapped in and out per second since the last run, Linux only
+  pub swap_in: Option<f64>,
+  pub swap_out: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -71,6 +178,165 @@ pub struct DiskStats {
   pub r#type: String,
   pub total: u64,
   pub used: u64,
+  /// Inode counts, missing where the file system has none to report
+  pub inodes_total: Option<u64>,
+  pub inodes_used: Option<u64>,
+  pub inodes_free: Option<u64>,
+  pub read_only: Option<bool>,
+  /// The mount options, on Linux the file system's own options follow the mount's
+  pub options: Option<Vec<String>>,
+  /// The kernel's name for the block device it's on, like sda1 or dm-0
+  pub device: Option<String>,
+  /// A network file system that didn't answer in time, its usage is left at 0
+  pub timed_out: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProcessStats {
+  pub pid: u32,
+  pub name: String,
+  /// Missing where processes have no owner, e.g. on Windows
+  pub user: Option<String>,
+  /// Truncated and redacted, missing when turned off or for kernel threads
+  pub command: Option<String>,
+  pub state: String,
+  /// Seconds since the epoch
+  pub start_time: u64,
+  pub cpu_usage: f32,
+  /// In KB, same as RAMStats
+  pub memory: u64,
+  /// Bytes per second since the last run
+  pub disk_read: u64,
+  pub disk_write: u64,
+}
+
+/// I/O of a block device since the last run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockDeviceStats {
+  pub name: String,
+  /// Where the device is mounted, including its partitions when they aren't reported on their own
+  pub mounts: Vec<String>,
+  /// Per second
+  pub read_bytes: f64,
+  pub write_bytes: f64,
+  pub read_iops: f64,
+  pub write_iops: f64,
+  /// Average milliseconds an I/O took including the time it was queued
+  pub read_await: f64,
+  pub write_await: f64,
+  /// Average number of I/Os queued or being served
+  pub queue_depth: f64,
+  /// Percentage of the time the device was busy
+  pub utilization: f64,
+  /// I/Os in progress right now
+  pub in_flight: u64,
+}
+
+/// Pressure stall information of the whole system and some of the cgroups
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PressureStats {
+  pub cpu: Option<Pressure>,
+  pub memory: Option<Pressure>,
+  pub io: Option<Pressure>,
+  /// By path under the cgroup v2 mount
+  pub cgroups: BTreeMap<String, CgroupPressure>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CgroupPressure {
+  pub cpu: Option<Pressure>,
+  pub memory: Option<Pressure>,
+  pub io: Option<Pressure>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pressure {
+  /// Time at least one task was stalled on the resource
+  pub some: PressureLine,
+  /// Time all tasks were stalled at once, missing for the CPU on older kernels
+  pub full: Option<PressureLine>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PressureLine {
+  /// Percentages of the last 10, 60 and 300 seconds
+  pub avg10: f32,
+  pub avg60: f32,
+  pub avg300: f32,
+  /// Microseconds since boot
+  pub total: u64,
+}
+
+/// Resource usage of a cgroup v2 group like a container or a systemd slice
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CgroupStats {
+  /// Path under the cgroup v2 mount
+  pub path: String,
+  /// Missing for groups that aren't containers
+  pub container: Option<ContainerInfo>,
+  /// Percent of one core like a process' cpu_usage, 0 on the first run that sees the group
+  pub cpu_usage: f32,
+  /// How many cores the group may use, missing without a limit
+  pub cpu_limit: Option<f32>,
+  /// Percent of the scheduler periods the group was throttled in
+  pub throttled: f32,
+  /// Seconds spent throttled per second
+  pub throttled_time: f32,
+  /// In KB, same as RAMStats
+  pub memory: Option<u64>,
+  /// Missing without a limit
+  pub memory_max: Option<u64>,
+  /// Bytes per second
+  pub io_read: Option<f64>,
+  pub io_write: Option<f64>,
+  pub pids: Option<u64>,
+  /// Missing without a limit
+  pub pids_max: Option<u64>,
+}
+
+/// A container as its runtime describes it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContainerInfo {
+  pub id: String,
+  /// docker, podman, containerd or cri-o
+  pub runtime: String,
+  /// Missing when the runtime's metadata can't be read, usually for lack of permissions
+  pub name: Option<String>,
+  pub image: Option<String>,
+  pub labels: BTreeMap<String, String>,
+}
+
+/// Health of the systemd units the config asks for
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SystemdStats {
+  pub units: Vec<SystemdUnitStats>,
+  /// Names of the units in the failed state, for the backend to alert on
+  pub failed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SystemdUnitStats {
+  /// Like `nginx.service`
+  pub name: String,
+  /// Like loaded, not-found or masked
+  pub load_state: String,
+  /// Like active, inactive, activating or failed
+  pub active_state: String,
+  /// Depends on the type of unit, like running or exited for services
+  pub sub_state: String,
+  /// Why the unit last stopped like exit-code or timeout, success when it didn't fail
+  pub result: Option<String>,
+  /// How many times systemd restarted the service, services only
+  pub restarts: Option<u32>,
+  /// In KB, same as RAMStats, missing without memory accounting
+  pub memory: Option<u64>,
+  /// Percent of one core like a process' cpu_usage, 0 on the first run that sees the unit,
+  /// missing without CPU accounting
+  pub cpu_usage: Option<f32>,
+  /// Seconds of CPU time since the unit was started
+  pub cpu_time: Option<f64>,
+  /// Seconds since the active state last changed, missing if it never did since boot
+  pub state_age: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -78,3 +344,39 @@ pub struct TempStats {
   pub label: String,
   pub value: f32,
 }
+
+/// Upper bounds in milliseconds of the latency histogram buckets,
+/// the last bucket counts everything above the last bound
+pub const HISTOGRAM_BOUNDS_MS: [u64; 10] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 5000];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LatencyHistogram {
+  /// Counts per bucket of HISTOGRAM_BOUNDS_MS plus one for anything slower
+  pub buckets: Vec<u64>,
+  pub count: u64,
+  pub sum_ms: f64,
+  pub max_ms: f64,
+  pub last_ms: f64,
+}
+
+/// How the reporter itself is doing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReporterStats {
+  /// Collection times per collector, only counted when they ran
+  pub collection: BTreeMap<String, LatencyHistogram>,
+  pub send: LatencyHistogram,
+  pub reconnects: u64,
+  /// Samples that couldn't be collected or sent
+  pub dropped_samples: u64,
+  /// Bytes of the samples sent so far that the backend hasn't acknowledged yet, missing
+  /// when offline or not on Linux. Samples aren't queued by the reporter, each one is
+  /// written to the connection as it's collected
+  pub queue_depth: Option<u64>,
+  /// Ticks that took longer than the interval
+  pub overruns: u64,
+  /// Ticks that didn't happen because the previous one ran past them
+  pub skipped_ticks: u64,
+  /// Resident memory of the reporter in KB, same as RAMStats
+  pub memory: u64,
+  pub cpu_usage: f32,
+}
@@ -2,17 +2,24 @@ use anyhow::Result;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::net::TcpStream;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
 use std::sync::Arc;
 use websocket::sync::stream::TlsStream;
 use websocket::sync::Client;
 use websocket::{ClientBuilder, Message};
 
 use crate::types::{
-  CPUStats, DiskStats, GPUStats, NetworkInterfaceStats, RAMStats, SwapStats, TempStats,
+  BlockDeviceStats, CPUStats, CgroupStats, CollectorError, DiskStats, GPUStats,
+  NetworkInterfaceStats, PressureStats, ProcessStats, RAMStats, ReporterStats, SwapStats,
+  SystemdStats, TempStats,
 };
 use crate::util::arcmutex;
 
+// Events are built right before they're sent, so the size of the samples doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum WebsocketEvent {
@@ -20,16 +27,28 @@ pub enum WebsocketEvent {
     auth_token: String,
   },
   DynamicData {
-    cpu: CPUStats,
-    ram: RAMStats,
-    swap: SwapStats,
+    cpu: Option<CPUStats>,
+    ram: Option<RAMStats>,
+    swap: Option<SwapStats>,
     gpu: Option<GPUStats>,
-    process_count: i32,
-    disks: Vec<DiskStats>,
+    process_count: Option<i32>,
+    disks: Option<Vec<DiskStats>>,
+    network_disks: Option<Vec<DiskStats>>,
     temps: Option<Vec<TempStats>>,
-    network: Vec<NetworkInterfaceStats>,
+    network: Option<Vec<NetworkInterfaceStats>>,
+    top_processes: Option<Vec<ProcessStats>>,
+    pressure: Option<PressureStats>,
+    cgroups: Option<Vec<CgroupStats>>,
+    block_devices: Option<Vec<BlockDeviceStats>>,
+    systemd: Option<SystemdStats>,
     host_uptime: u64,
     reporter_uptime: u64,
+    reporter: Option<ReporterStats>,
+    errors: BTreeMap<String, CollectorError>,
+    extra: BTreeMap<String, Value>,
+  },
+  Offline {
+    reason: String,
   },
   StaticData {
     hostname: Option<String>,
@@ -45,6 +64,9 @@ pub enum WebsocketEvent {
     cpu_threads: usize,
     total_mem: u64,
     reporter_version: String,
+    container: Option<String>,
+    virtualization: Option<String>,
+    extra: BTreeMap<String, Value>,
   },
 }
 
@@ -53,6 +75,7 @@ pub fn get_event_id(ev: &WebsocketEvent) -> &str {
     WebsocketEvent::Login { .. } => "login",
     WebsocketEvent::StaticData { .. } => "static-data",
     WebsocketEvent::DynamicData { .. } => "dynamic-data",
+    WebsocketEvent::Offline { .. } => "offline",
   }
 }
 
@@ -69,6 +92,7 @@ pub struct WebsocketManager {
 
 impl WebsocketManager {
   pub fn new(websocket_url: &str) -> Result<Self> {
+    info!("Connecting to {}", websocket_url);
     let mut client = ClientBuilder::new(websocket_url)?;
     Ok(Self {
       websocket_url: websocket_url.to_string(),
@@ -77,6 +101,7 @@ impl WebsocketManager {
   }
 
   pub fn send(&mut self, data: WebsocketEvent) -> Result<()> {
+    trace!("Sending {} event", get_event_id(&data));
     let message = Message::text(
       json!({
           "e": get_event_id(&data),
@@ -87,4 +112,29 @@ impl WebsocketManager {
 
     Ok(self.websocket.lock().send_message(&message)?)
   }
+
+  /// Bytes written to the connection that the backend hasn't acknowledged yet, which is
+  /// what's waiting since samples are sent as they're collected. None when it can't be told
+  pub fn queued_bytes(&self) -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+      let fd = self.websocket.lock().stream_ref().get_ref().as_raw_fd();
+      let mut queued: libc::c_int = 0;
+      // SAFETY: TIOCOUTQ only writes the size of the socket's send queue to the int it's given
+      let result = unsafe { libc::ioctl(fd, libc::TIOCOUTQ, &mut queued) };
+      (result == 0).then_some(queued as u64)
+    }
+    #[cfg(not(target_os = "linux"))]
+    None
+  }
+
+  /// Sends a close frame telling the backend we're going away and shuts down the connection
+  pub fn close(&mut self) -> Result<()> {
+    debug!("Closing the connection to {}", self.websocket_url);
+    let mut websocket = self.websocket.lock();
+    let closed = websocket.send_message(&Message::close_because(1001, "Reporter shutting down"));
+    // The socket is shut down even when the close frame couldn't be sent
+    websocket.shutdown()?;
+    Ok(closed?)
+  }
 }